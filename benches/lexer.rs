@@ -0,0 +1,46 @@
+//! Times the lexer on generated programs of doubling size; the time per KB
+//! should stay flat. Run with `cargo bench --bench lexer`.
+
+extern crate monkey_rs;
+
+use monkey_rs::lexer::Lexer;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// a program of at least `size` bytes exercising every kind of token.
+fn program(size: usize) -> String {
+    let chunk = "// a line comment\n/* a /* nested */ block comment */\n\
+                 let add_two = fn(x, y) { return x * 2 + y / 3 % 4; };\n\
+                 let s = \"a string with \\\"escapes\\\" \\u{1F600}\";\n\
+                 let h = {\"key\": [1_000, 0xff, 2.5, 3e-2], 10: true && !false || x >= 12345};\n\
+                 while (i <= 1000) { if (i != 3) { break; } else { continue; } }\n";
+    chunk.repeat(size / chunk.len() + 1)
+}
+
+// the fastest of a few runs of lexing `src` to the end.
+fn time(src: &str) -> Duration {
+    (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            for t in Lexer::new(src) {
+                black_box(t.unwrap());
+            }
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    println!("{:>8} {:>12} {:>12}", "KB", "time", "ns/KB");
+    for kb in [64, 128, 256, 512, 1024] {
+        let src = program(kb * 1024);
+        let elapsed = time(&src);
+        println!(
+            "{:>8} {:>12.2?} {:>12}",
+            kb,
+            elapsed,
+            elapsed.as_nanos() / kb as u128
+        );
+    }
+}
@@ -1,25 +1,57 @@
-use token::Token;
+use lexer::LexError;
+#[cfg(not(feature = "bigint"))]
+use lexer::LexErrorKind;
+#[cfg(feature = "bigint")]
+use num_bigint::BigInt;
+use span::Span;
+use std::collections::VecDeque;
+use std::error;
+use std::fmt;
+use token::{SpannedToken, Token};
 
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum ASTKind {
-    Int(i32),
+    Int(i64),
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt),
+    Float(f64),
+    Str(String),
     Ident(String),
     Bool(bool),
+    Neg(Box<AST>),
+    Not(Box<AST>),
     Add(Box<AST>, Box<AST>),
     Minus(Box<AST>, Box<AST>),
     Multi(Box<AST>, Box<AST>),
+    Div(Box<AST>, Box<AST>),
+    Mod(Box<AST>, Box<AST>),
     LT(Box<AST>, Box<AST>),
     LTE(Box<AST>, Box<AST>),
     GT(Box<AST>, Box<AST>),
     GTE(Box<AST>, Box<AST>),
+    Eq(Box<AST>, Box<AST>),
+    NotEq(Box<AST>, Box<AST>),
+    /// `&&`; the right operand is only evaluated if the left one is truthy.
+    And(Box<AST>, Box<AST>),
+    /// `||`; the right operand is only evaluated if the left one is falsy.
+    Or(Box<AST>, Box<AST>),
     Let {
         name: String,
         expr: Box<AST>,
     },
+    /// `name = expr;`, which rebinds a variable in the nearest scope that
+    /// has it, unlike `let`.
+    Assign {
+        name: String,
+        expr: Box<AST>,
+    },
     While {
         cond: Box<AST>,
         stmt: Box<AST>,
     },
+    Break,
+    Continue,
     Return(Box<AST>),
     Compound(Vec<AST>),
     If {
@@ -28,425 +60,1017 @@ pub enum ASTKind {
         else_stmt: Option<Box<AST>>,
     },
     FnCall {
-        name: String,
+        callee: Box<AST>,
         args: Vec<AST>,
     },
     FnDef {
         args: Vec<String>,
         stmts: Vec<AST>,
     },
+    Array(Vec<AST>),
+    /// `{key: value, ...}`, the pairs in source order.
+    Hash(Vec<(AST, AST)>),
+    /// `left[index]`.
+    Index {
+        left: Box<AST>,
+        index: Box<AST>,
+    },
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// A node of the tree together with the source range it was parsed from.
+///
+/// Nodes built by hand (through the constructors below) have a default span.
+/// Equality only looks at `kind`, so such nodes compare equal to parsed ones.
+#[derive(Clone, Debug)]
 pub struct AST {
     pub kind: ASTKind,
+    pub span: Span,
+}
+
+impl PartialEq for AST {
+    fn eq(&self, other: &AST) -> bool {
+        self.kind == other.kind
+    }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    /// Source the lexer could not turn into a token.
+    Lex(LexError),
+    /// A token that cannot start or continue the construct being parsed.
+    UnexpectedToken {
+        found: Token,
+        span: Span,
+    },
+    /// A specific token was required, e.g. the `;` after a statement.
+    Expected {
+        expected: Token,
+        found: Token,
+        span: Span,
+    },
+    ExpectedIdent {
+        found: Token,
+        span: Span,
+    },
+    /// A `{` whose matching `}` never came; `span` is the `{`.
+    UnterminatedBlock {
+        span: Span,
+    },
+    /// `break` or `continue` that is not inside a `while` body.
+    OutsideLoop {
+        keyword: Token,
+        span: Span,
+    },
+    /// A function parameter named twice; `span` is the second one.
+    DuplicateParameter {
+        name: String,
+        span: Span,
+    },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match *self {
+            ParseError::Lex(ref e) => e.span,
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::Expected { span, .. }
+            | ParseError::ExpectedIdent { span, .. }
+            | ParseError::UnterminatedBlock { span }
+            | ParseError::OutsideLoop { span, .. }
+            | ParseError::DuplicateParameter { span, .. } => span,
+        }
+    }
+}
+
+fn describe(t: &Token) -> String {
+    match *t {
+        Token::EOF => "end of input".to_string(),
+        Token::Ident(ref s) => format!("identifier `{}`", s),
+        ref t => format!("`{}`", t),
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Lex(ref e) => write!(f, "{}", e),
+            ParseError::UnexpectedToken { ref found, .. } => {
+                write!(f, "unexpected {}", describe(found))
+            }
+            ParseError::Expected {
+                ref expected,
+                ref found,
+                ..
+            } => write!(f, "expected `{}`, found {}", expected, describe(found)),
+            ParseError::ExpectedIdent { ref found, .. } => {
+                write!(f, "expected identifier, found {}", describe(found))
+            }
+            ParseError::UnterminatedBlock { .. } => write!(f, "unclosed `{{`"),
+            ParseError::OutsideLoop { ref keyword, .. } => {
+                write!(f, "`{}` outside of a loop", keyword)
+            }
+            ParseError::DuplicateParameter { ref name, .. } => {
+                write!(f, "parameter `{}` is declared more than once", name)
+            }
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+// the literal `n` at `span`, larger than `i64::MAX` and not negated: a big
+// integer or an error depending on the `bigint` feature.
+#[cfg(feature = "bigint")]
+fn int_too_large(n: u64, _: Span) -> Result<AST, ParseError> {
+    Ok(AST::bigint(BigInt::from(n)))
+}
+
+#[cfg(not(feature = "bigint"))]
+fn int_too_large(_: u64, span: Span) -> Result<AST, ParseError> {
+    Err(ParseError::Lex(LexError {
+        kind: LexErrorKind::IntegerTooLarge,
+        span,
+    }))
+}
+
+/// How tightly an operator binds its operands, loosest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Lowest,
+    Or,
+    And,
+    Equality,
+    Relational,
+    Sum,
+    Product,
+    Prefix,
+    Call,
+}
+
+// what an operator following its left operand builds.
+#[derive(Clone, Copy)]
+enum Infix {
+    /// `(`, which starts the argument list of a call.
+    Call,
+    /// `[`, which starts an index expression.
+    Index,
+    /// A binary operator, with the constructor of its node.
+    Binary(fn(AST, AST) -> AST),
+}
+
+// the operators that follow their left operand, their precedences and what
+// they build. Keeping the constructors here means that every operator with a
+// precedence can be parsed.
+fn infix_operator(t: &Token) -> Option<(Precedence, Infix)> {
+    let op = match *t {
+        Token::LParen => (Precedence::Call, Infix::Call),
+        Token::LBracket => (Precedence::Call, Infix::Index),
+        Token::Or => (Precedence::Or, Infix::Binary(AST::or)),
+        Token::And => (Precedence::And, Infix::Binary(AST::and)),
+        Token::Eq => (Precedence::Equality, Infix::Binary(AST::eq)),
+        Token::NotEq => (Precedence::Equality, Infix::Binary(AST::not_eq)),
+        Token::LT => (Precedence::Relational, Infix::Binary(AST::lt)),
+        Token::LTE => (Precedence::Relational, Infix::Binary(AST::lte)),
+        Token::GT => (Precedence::Relational, Infix::Binary(AST::gt)),
+        Token::GTE => (Precedence::Relational, Infix::Binary(AST::gte)),
+        Token::Plus => (Precedence::Sum, Infix::Binary(AST::add)),
+        Token::Minus => (Precedence::Sum, Infix::Binary(AST::minus)),
+        Token::Star => (Precedence::Product, Infix::Binary(AST::multi)),
+        Token::Slash => (Precedence::Product, Infix::Binary(AST::div)),
+        Token::Percent => (Precedence::Product, Infix::Binary(AST::modulo)),
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// Parses tokens as it reads them, so lexing and parsing happen together.
 pub struct Parser<'a> {
-    tokens: &'a [Token],
+    // the tokens not read yet.
+    lexer: Box<dyn Iterator<Item = Result<SpannedToken, LexError>> + 'a>,
+    // the tokens read but not consumed yet, and the one consumed last, for
+    // `prev_span` and for `brace_statement` to back up over its `{`. The
+    // first of them is token number `base` of the input.
+    tokens: VecDeque<SpannedToken>,
+    base: usize,
+    // number of the next token of the input.
     index: usize,
-    pub result: Vec<AST>,
+    errors: Vec<ParseError>,
+    // lexical errors, with the number of the token after each.
+    lex_errors: Vec<(usize, LexError)>,
+    // number of `while` bodies around the current position, within the
+    // innermost function.
+    loop_depth: usize,
 }
 
 impl AST {
-    pub fn int(i: i32) -> AST {
+    fn new(kind: ASTKind) -> AST {
         AST {
-            kind: ASTKind::Int(i),
+            kind,
+            span: Span::default(),
         }
     }
 
+    pub fn with_span(mut self, span: Span) -> AST {
+        self.span = span;
+        self
+    }
+
+    pub fn int(i: i64) -> AST {
+        AST::new(ASTKind::Int(i))
+    }
+
+    #[cfg(feature = "bigint")]
+    pub fn bigint(b: BigInt) -> AST {
+        AST::new(ASTKind::BigInt(b))
+    }
+
+    pub fn float(x: f64) -> AST {
+        AST::new(ASTKind::Float(x))
+    }
+
+    pub fn str(s: String) -> AST {
+        AST::new(ASTKind::Str(s))
+    }
+
     pub fn ident(s: String) -> AST {
-        AST {
-            kind: ASTKind::Ident(s),
-        }
+        AST::new(ASTKind::Ident(s))
     }
 
     pub fn bool(b: bool) -> AST {
-        AST {
-            kind: ASTKind::Bool(b),
-        }
+        AST::new(ASTKind::Bool(b))
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn neg(expr: AST) -> AST {
+        AST::new(ASTKind::Neg(Box::new(expr)))
     }
 
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(expr: AST) -> AST {
+        AST::new(ASTKind::Not(Box::new(expr)))
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn add(left: AST, right: AST) -> AST {
-        AST {
-            kind: ASTKind::Add(Box::new(left), Box::new(right)),
-        }
+        AST::new(ASTKind::Add(Box::new(left), Box::new(right)))
     }
 
     pub fn minus(left: AST, right: AST) -> AST {
-        AST {
-            kind: ASTKind::Minus(Box::new(left), Box::new(right)),
-        }
+        AST::new(ASTKind::Minus(Box::new(left), Box::new(right)))
     }
 
     pub fn multi(left: AST, right: AST) -> AST {
-        AST {
-            kind: ASTKind::Multi(Box::new(left), Box::new(right)),
-        }
+        AST::new(ASTKind::Multi(Box::new(left), Box::new(right)))
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn div(left: AST, right: AST) -> AST {
+        AST::new(ASTKind::Div(Box::new(left), Box::new(right)))
+    }
+
+    pub fn modulo(left: AST, right: AST) -> AST {
+        AST::new(ASTKind::Mod(Box::new(left), Box::new(right)))
     }
 
     pub fn let_stmt(name: String, expr: AST) -> AST {
-        AST {
-            kind: ASTKind::Let {
-                name,
-                expr: Box::new(expr),
-            },
-        }
+        AST::new(ASTKind::Let {
+            name,
+            expr: Box::new(expr),
+        })
+    }
+
+    pub fn assign_stmt(name: String, expr: AST) -> AST {
+        AST::new(ASTKind::Assign {
+            name,
+            expr: Box::new(expr),
+        })
     }
 
     pub fn while_stmt(cond: AST, stmt: AST) -> AST {
-        AST {
-            kind: ASTKind::While {
-                cond: Box::new(cond),
-                stmt: Box::new(stmt),
-            },
-        }
+        AST::new(ASTKind::While {
+            cond: Box::new(cond),
+            stmt: Box::new(stmt),
+        })
+    }
+
+    pub fn break_stmt() -> AST {
+        AST::new(ASTKind::Break)
+    }
+
+    pub fn continue_stmt() -> AST {
+        AST::new(ASTKind::Continue)
     }
 
     pub fn return_stmt(expr: AST) -> AST {
-        AST {
-            kind: ASTKind::Return(Box::new(expr)),
-        }
+        AST::new(ASTKind::Return(Box::new(expr)))
     }
 
     pub fn compound_statement(stmts: Vec<AST>) -> AST {
-        AST {
-            kind: ASTKind::Compound(stmts),
-        }
+        AST::new(ASTKind::Compound(stmts))
     }
 
     pub fn if_stmt(cond: AST, stmt: AST, else_stmt: Option<AST>) -> AST {
-        if let Some(e) = else_stmt {
-            AST {
-                kind: ASTKind::If {
-                    cond: Box::new(cond),
-                    stmt: Box::new(stmt),
-                    else_stmt: Some(Box::new(e)),
-                },
-            }
-        } else {
-            AST {
-                kind: ASTKind::If {
-                    cond: Box::new(cond),
-                    stmt: Box::new(stmt),
-                    else_stmt: None,
-                },
-            }
-        }
+        AST::new(ASTKind::If {
+            cond: Box::new(cond),
+            stmt: Box::new(stmt),
+            else_stmt: else_stmt.map(Box::new),
+        })
     }
 
-    pub fn fn_call(name: String, args: Vec<AST>) -> AST {
-        AST {
-            kind: ASTKind::FnCall { name, args },
-        }
+    pub fn fn_call(callee: AST, args: Vec<AST>) -> AST {
+        AST::new(ASTKind::FnCall {
+            callee: Box::new(callee),
+            args,
+        })
     }
 
     pub fn fn_def(args: Vec<String>, stmts: Vec<AST>) -> AST {
-        AST {
-            kind: ASTKind::FnDef { args, stmts },
-        }
+        AST::new(ASTKind::FnDef { args, stmts })
+    }
+
+    pub fn array(elements: Vec<AST>) -> AST {
+        AST::new(ASTKind::Array(elements))
+    }
+
+    pub fn hash(pairs: Vec<(AST, AST)>) -> AST {
+        AST::new(ASTKind::Hash(pairs))
+    }
+
+    pub fn index(left: AST, index: AST) -> AST {
+        AST::new(ASTKind::Index {
+            left: Box::new(left),
+            index: Box::new(index),
+        })
     }
 
     pub fn lt(left: AST, right: AST) -> AST {
-        AST {
-            kind: ASTKind::LT(Box::new(left), Box::new(right)),
-        }
+        AST::new(ASTKind::LT(Box::new(left), Box::new(right)))
     }
 
-    fn binary(op: Token, left: AST, right: AST) -> AST {
-        match op {
-            Token::Plus => AST::add(left, right),
-            Token::Minus => AST::minus(left, right),
-            Token::LT => AST::lt(left, right),
-            _ => unimplemented!(),
-        }
+    pub fn lte(left: AST, right: AST) -> AST {
+        AST::new(ASTKind::LTE(Box::new(left), Box::new(right)))
+    }
+
+    pub fn gt(left: AST, right: AST) -> AST {
+        AST::new(ASTKind::GT(Box::new(left), Box::new(right)))
+    }
+
+    pub fn gte(left: AST, right: AST) -> AST {
+        AST::new(ASTKind::GTE(Box::new(left), Box::new(right)))
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn eq(left: AST, right: AST) -> AST {
+        AST::new(ASTKind::Eq(Box::new(left), Box::new(right)))
+    }
+
+    pub fn not_eq(left: AST, right: AST) -> AST {
+        AST::new(ASTKind::NotEq(Box::new(left), Box::new(right)))
+    }
+
+    pub fn and(left: AST, right: AST) -> AST {
+        AST::new(ASTKind::And(Box::new(left), Box::new(right)))
+    }
+
+    pub fn or(left: AST, right: AST) -> AST {
+        AST::new(ASTKind::Or(Box::new(left), Box::new(right)))
     }
 }
 
 impl<'a> Parser<'a> {
-    fn return_stmt(&mut self) -> AST {
-        assert_eq!(self.get(), Some(Token::Return));
-        AST::return_stmt(self.expression_statement())
+    fn return_stmt(&mut self) -> Result<AST, ParseError> {
+        let start = self.expect(Token::Return)?;
+        let expr = self.expression_statement()?;
+        Ok(AST::return_stmt(expr).with_span(start.to(self.prev_span())))
     }
 
-    fn let_stmt(&mut self) -> AST {
-        assert_eq!(self.get(), Some(Token::Let));
-        let name = match self.peek() {
-            Some(Token::Ident(s)) => {
-                self.get();
-                s
+    fn let_stmt(&mut self) -> Result<AST, ParseError> {
+        let start = self.expect(Token::Let)?;
+        let name = self.ident()?;
+        self.expect(Token::Assign)?;
+        let expr = self.expression()?;
+        self.expect(Token::Semicolon)?;
+        Ok(AST::let_stmt(name, expr).with_span(start.to(self.prev_span())))
+    }
+
+    fn assign_stmt(&mut self) -> Result<AST, ParseError> {
+        let start = self.peek_span();
+        let name = self.ident()?;
+        self.expect(Token::Assign)?;
+        let expr = self.expression()?;
+        self.expect(Token::Semicolon)?;
+        Ok(AST::assign_stmt(name, expr).with_span(start.to(self.prev_span())))
+    }
+
+    // parses `{ stmt* }`. A statement that fails to parse is recorded and
+    // skipped, so the rest of the block is still checked.
+    fn block(&mut self) -> Result<Vec<AST>, ParseError> {
+        let open = self.expect(Token::LBrace)?;
+        self.block_rest(open, vec![])
+    }
+
+    // parses the statements of a block after `stmts`, up to and including
+    // its `}`. `open` is the span of its `{`.
+    fn block_rest(&mut self, open: Span, mut stmts: Vec<AST>) -> Result<Vec<AST>, ParseError> {
+        loop {
+            match self.peek() {
+                Some(Token::RBrace) => break,
+                None | Some(Token::EOF) => {
+                    return Err(ParseError::UnterminatedBlock { span: open })
+                }
+                _ => {}
             }
-            _ => panic!("parse error: expect ident but got {:?}", self.peek()),
-        };
-        assert_eq!(self.get(), Some(Token::Assign));
-        let expr = self.expression();
-        assert_eq!(self.get(), Some(Token::Semicolon));
-        AST::let_stmt(name, expr)
+            let index = self.index;
+            match self.statement() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(e) => {
+                    self.report(e, index);
+                    self.synchronize(index);
+                }
+            }
+        }
+        self.get();
+        Ok(stmts)
     }
 
-    fn compound_statement(&mut self) -> AST {
+    // parses a statement starting with `{`: a block, or an expression
+    // statement starting with a hash literal. The expression after the `{`
+    // is parsed once, and is the first key of a hash if a `:` follows it, or
+    // else the first statement of the block. `{}` is an empty block.
+    fn brace_statement(&mut self) -> Result<AST, ParseError> {
+        let start = self.peek_span();
+        let index = self.index;
         self.get();
+        let is_block = match self.peek() {
+            Some(Token::RBrace)
+            | Some(Token::LBrace)
+            | Some(Token::Let)
+            | Some(Token::Return)
+            | Some(Token::If)
+            | Some(Token::While)
+            | Some(Token::Break)
+            | Some(Token::Continue) => true,
+            Some(Token::Ident(_)) => self.peek_nth(1) == Some(Token::Assign),
+            _ => false,
+        };
+        if is_block {
+            self.index = index;
+            let stmts = self.block()?;
+            return Ok(AST::compound_statement(stmts).with_span(start.to(self.prev_span())));
+        }
+        let first = self.index;
         let mut stmts = vec![];
-        while self.peek() != Some(Token::RBrace) {
-            stmts.push(self.statement());
+        match self.expression() {
+            Ok(key) if self.peek() == Some(Token::Colon) => {
+                let hash = self.hash_rest(start, key)?;
+                let expr = self.infix(hash, Precedence::Lowest)?;
+                self.expect(Token::Semicolon)?;
+                return Ok(expr);
+            }
+            Ok(expr) => match self.expect(Token::Semicolon) {
+                Ok(_) => stmts.push(expr),
+                Err(e) => {
+                    self.report(e, first);
+                    self.synchronize(first);
+                }
+            },
+            Err(e) => {
+                self.report(e, first);
+                self.synchronize(first);
+            }
         }
-        self.get();
-        AST::compound_statement(stmts)
+        let stmts = self.block_rest(start, stmts)?;
+        Ok(AST::compound_statement(stmts).with_span(start.to(self.prev_span())))
     }
 
-    fn if_stmt(&mut self) -> AST {
-        self.get();
-        let cond = self.expression();
-        let stmt = self.statement();
+    // parses the rest of a hash literal starting at `start`, whose first
+    // key has just been parsed.
+    fn hash_rest(&mut self, start: Span, key: AST) -> Result<AST, ParseError> {
+        self.expect(Token::Colon)?;
+        let mut pairs = vec![(key, self.expression()?)];
+        if self.peek() == Some(Token::Comma) {
+            self.get();
+            pairs.extend(self.comma_separated(Token::RBrace, |p| p.hash_entry())?);
+        } else {
+            self.expect(Token::RBrace)?;
+        }
+        Ok(AST::hash(pairs).with_span(start.to(self.prev_span())))
+    }
+
+    // parses a `key: value` entry of a hash literal.
+    fn hash_entry(&mut self) -> Result<(AST, AST), ParseError> {
+        let key = self.expression()?;
+        self.expect(Token::Colon)?;
+        Ok((key, self.expression()?))
+    }
+
+    fn if_stmt(&mut self) -> Result<AST, ParseError> {
+        let start = self.expect(Token::If)?;
+        let cond = self.expression()?;
+        let stmt = self.statement()?;
         let else_stmt = match self.peek() {
             Some(Token::Else) => {
                 self.get();
-                Some(self.statement())
+                Some(self.statement()?)
             }
             _ => None,
         };
-        AST::if_stmt(cond, stmt, else_stmt)
+        Ok(AST::if_stmt(cond, stmt, else_stmt).with_span(start.to(self.prev_span())))
     }
 
-    fn while_stmt(&mut self) -> AST {
-        self.get();
-        let cond = self.expression();
+    fn while_stmt(&mut self) -> Result<AST, ParseError> {
+        let start = self.expect(Token::While)?;
+        let cond = self.expression()?;
+        self.loop_depth += 1;
         let stmt = self.statement();
-        AST::while_stmt(cond, stmt)
+        self.loop_depth -= 1;
+        Ok(AST::while_stmt(cond, stmt?).with_span(start.to(self.prev_span())))
     }
 
-    fn peek(&self) -> Option<Token> {
-        if let Some(t) = self.tokens.get(self.index) {
-            Some(t.clone())
-        } else {
-            None
+    // parses `break;` or `continue;`.
+    fn loop_control(&mut self) -> Result<AST, ParseError> {
+        let start = self.peek_span();
+        let keyword = self.get().unwrap_or(Token::EOF);
+        if self.loop_depth == 0 {
+            return Err(ParseError::OutsideLoop {
+                keyword,
+                span: start,
+            });
         }
+        self.expect(Token::Semicolon)?;
+        let ast = match keyword {
+            Token::Break => AST::break_stmt(),
+            _ => AST::continue_stmt(),
+        };
+        Ok(ast.with_span(start.to(self.prev_span())))
+    }
+
+    // reads tokens until the one at `index` has been read, or the input
+    // ends. Lexical errors are set aside, and the parser sees the tokens
+    // around them.
+    fn fill(&mut self, index: usize) {
+        while self.base + self.tokens.len() <= index {
+            match self.lexer.next() {
+                Some(Ok(SpannedToken {
+                    token: Token::Comment(_),
+                    ..
+                })) => {}
+                Some(Ok(t)) => self.tokens.push_back(t),
+                Some(Err(e)) => {
+                    let index = self.base + self.tokens.len();
+                    self.lex_errors.push((index, e))
+                }
+                None => return,
+            }
+        }
+    }
+
+    // the token numbered `index`, if it has been read and not dropped.
+    fn token(&self, index: usize) -> Option<&SpannedToken> {
+        let i = index.checked_sub(self.base)?;
+        self.tokens.get(i)
+    }
+
+    fn peek(&mut self) -> Option<Token> {
+        self.peek_nth(0)
+    }
+
+    // the token `n` tokens after the next one.
+    fn peek_nth(&mut self, n: usize) -> Option<Token> {
+        self.fill(self.index + n);
+        self.token(self.index + n).map(|t| t.token.clone())
     }
 
     fn get(&mut self) -> Option<Token> {
-        if let Some(t) = self.tokens.get(self.index) {
+        let t = self.peek();
+        if t.is_some() {
             self.index += 1;
-            Some(t.clone())
-        } else {
-            None
+            // keep only the token just consumed behind `index`.
+            while self.base + 1 < self.index {
+                self.tokens.pop_front();
+                self.base += 1;
+            }
         }
+        t
     }
 
-    pub fn new(tokens: &'a [Token]) -> Self {
-        Parser {
-            tokens,
-            index: 0,
-            result: vec![],
+    // span of the next token, or of the end of input when there is none.
+    fn peek_span(&mut self) -> Span {
+        self.fill(self.index);
+        match self.token(self.index) {
+            Some(t) => t.span,
+            None => match self.tokens.back() {
+                Some(t) => Span::new(t.span.end, t.span.end),
+                None => Span::default(),
+            },
         }
     }
 
-    fn primary(&mut self) -> AST {
-        let t = self.get();
-        match t {
-            Some(Token::Int(i)) => AST::int(i),
-            Some(Token::Ident(s)) => {
-                if let Some(Token::LParen) = self.peek() {
-                    self.get();
-                    let mut args = vec![];
-                    loop {
-                        args.push(self.expression());
-                        match self.peek() {
-                            Some(Token::RParen) => break,
-                            Some(Token::Comma) => self.get(),
-                            Some(i) => panic!("parse error: unexpected token {:?}", i),
-                            None => panic!("nannka token nai nen kedo"),
-                        };
-                    }
-                    self.get();
-                    AST::fn_call(s, args)
-                } else {
-                    AST::ident(s)
-                }
+    // span of the token consumed last.
+    fn prev_span(&mut self) -> Span {
+        match self.index.checked_sub(1).and_then(|i| self.token(i)) {
+            Some(t) => t.span,
+            None => self.peek_span(),
+        }
+    }
+
+    // consumes the next token if it is `expected`, and returns its span.
+    fn expect(&mut self, expected: Token) -> Result<Span, ParseError> {
+        let span = self.peek_span();
+        match self.peek() {
+            Some(ref t) if *t == expected => {
+                self.get();
+                Ok(span)
             }
-            Some(Token::True) => AST::bool(true),
-            Some(Token::False) => AST::bool(false),
-            Some(Token::LParen) => {
-                let expr = self.expression();
-                assert_eq!(self.get(), Some(Token::RParen));
-                expr
+            None | Some(Token::EOF) => {
+                // point just past the last token rather than at the end of
+                // input, which may be lines further down.
+                let end = self.prev_span().end;
+                Err(ParseError::Expected {
+                    expected,
+                    found: Token::EOF,
+                    span: Span::new(end, end),
+                })
             }
-            Some(_) => panic!(
-                "unexpected token! {:?}, btw next one is {:?}",
-                t.unwrap(),
-                self.tokens.get(self.index + 1)
-            ),
-            None => panic!("parse error: try to parse primary but got None."),
+            Some(found) => Err(ParseError::Expected {
+                expected,
+                found,
+                span,
+            }),
         }
     }
 
-    fn additive(&mut self) -> AST {
-        let mut left = self.multiplicative();
-        loop {
-            match self.peek() {
-                Some(Token::Plus) | Some(Token::Minus) => {
-                    let op = self.get().unwrap();
-                    let right = self.multiplicative();
-                    left = AST::binary(op, left, right);
-                }
-                _ => break,
-            };
+    fn ident(&mut self) -> Result<String, ParseError> {
+        let span = self.peek_span();
+        match self.peek() {
+            Some(Token::Ident(s)) => {
+                self.get();
+                Ok(s)
+            }
+            found => Err(ParseError::ExpectedIdent {
+                found: found.unwrap_or(Token::EOF),
+                span,
+            }),
+        }
+    }
+
+    fn unexpected(&mut self) -> ParseError {
+        ParseError::UnexpectedToken {
+            found: self.peek().unwrap_or(Token::EOF),
+            span: self.peek_span(),
         }
-        left
     }
 
-    fn multiplicative(&mut self) -> AST {
-        let mut left = self.primary();
+    // records `e`, the error of a statement that started at token `start`,
+    // unless a lexical error inside the statement is the likely cause.
+    fn report(&mut self, e: ParseError, start: usize) {
+        let end = self.index;
+        if !self.lex_errors.iter().any(|&(i, _)| start <= i && i <= end) {
+            self.errors.push(e);
+        }
+    }
+
+    // skips tokens up to the next statement boundary after an error: past a
+    // `;`, or up to a `}` or a keyword that starts a statement. Whole `{ }`
+    // groups are skipped, as their contents belong to the broken statement.
+    // The statement that failed started at `start`; if it consumed nothing,
+    // its first token is skipped so that parsing always makes progress.
+    fn synchronize(&mut self, start: usize) {
+        if self.index == start {
+            if let Some(Token::Semicolon) = self.get() {
+                return;
+            }
+        }
+        let mut depth = 0;
         loop {
-            let peeked = self.peek();
-            if peeked != Some(Token::Star) {
-                break;
+            match self.peek() {
+                None | Some(Token::EOF) => return,
+                Some(Token::LBrace) => depth += 1,
+                Some(Token::RBrace) if depth > 0 => depth -= 1,
+                Some(Token::RBrace)
+                | Some(Token::Let)
+                | Some(Token::Return)
+                | Some(Token::If)
+                | Some(Token::While)
+                | Some(Token::Break)
+                | Some(Token::Continue)
+                    if depth == 0 =>
+                {
+                    return
+                }
+                Some(Token::Semicolon) if depth == 0 => {
+                    self.get();
+                    return;
+                }
+                _ => {}
             }
             self.get();
-            let right = self.primary();
-            left = AST::multi(left, right);
         }
-        left
     }
 
-    fn fn_def(&mut self) -> AST {
-        self.get();
-        let mut args = vec![];
-        assert_eq!(self.get(), Some(Token::LParen));
-        loop {
-            let t = self.get();
-            if let Some(s) = t {
-                if let Token::Ident(s) = s {
-                    args.push(s);
+    /// A parser reading from `tokens`, usually a `Lexer`. The stream may or
+    /// may not end with a `Token::EOF`, and comments in it are skipped.
+    pub fn new<I>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = Result<SpannedToken, LexError>>,
+        I::IntoIter: 'a,
+    {
+        Parser {
+            lexer: Box::new(tokens.into_iter().fuse()),
+            tokens: VecDeque::new(),
+            base: 0,
+            index: 0,
+            errors: vec![],
+            lex_errors: vec![],
+            loop_depth: 0,
+        }
+    }
+
+    // parses what can start an expression: a literal (including array and
+    // hash literals), a variable, a parenthesized expression, or a prefix
+    // operator applied to its operand.
+    fn prefix(&mut self) -> Result<AST, ParseError> {
+        let start = self.peek_span();
+        let ast = match self.peek() {
+            Some(Token::Minus) => {
+                self.get();
+                match self.peek() {
+                    // the one literal that only fits in an integer negated.
+                    Some(Token::Int(n)) if n == i64::MIN.unsigned_abs() => {
+                        self.get();
+                        AST::int(i64::MIN)
+                    }
+                    _ => AST::neg(self.expression_bp(Precedence::Prefix)?),
+                }
+            }
+            Some(Token::Bang) => {
+                self.get();
+                AST::not(self.expression_bp(Precedence::Prefix)?)
+            }
+            Some(Token::Function) => return self.fn_def(),
+            Some(Token::Int(n)) => {
+                self.get();
+                if n <= i64::MAX as u64 {
+                    AST::int(n as i64)
                 } else {
-                    panic!(
-                        "parse error: illegal token for args of function defition {:?}",
-                        s
-                    )
+                    int_too_large(n, start)?
                 }
+            }
+            #[cfg(feature = "bigint")]
+            Some(Token::BigInt(b)) => {
+                self.get();
+                AST::bigint(b)
+            }
+            Some(Token::Float(x)) => {
+                self.get();
+                AST::float(x)
+            }
+            Some(Token::Str(s)) => {
+                self.get();
+                AST::str(s)
+            }
+            Some(Token::Ident(s)) => {
+                self.get();
+                AST::ident(s)
+            }
+            Some(Token::True) => {
+                self.get();
+                AST::bool(true)
+            }
+            Some(Token::False) => {
+                self.get();
+                AST::bool(false)
+            }
+            Some(Token::LParen) => {
+                self.get();
+                let expr = self.expression()?;
+                self.expect(Token::RParen)?;
+                expr
+            }
+            Some(Token::LBracket) => {
+                self.get();
+                AST::array(self.comma_separated(Token::RBracket, |p| p.expression())?)
+            }
+            Some(Token::LBrace) => {
+                self.get();
+                AST::hash(self.comma_separated(Token::RBrace, |p| p.hash_entry())?)
+            }
+            _ => return Err(self.unexpected()),
+        };
+        Ok(ast.with_span(start.to(self.prev_span())))
+    }
+
+    fn fn_def(&mut self) -> Result<AST, ParseError> {
+        let start = self.expect(Token::Function)?;
+        self.expect(Token::LParen)?;
+        let params = self.comma_separated(Token::RParen, |p| {
+            let span = p.peek_span();
+            p.ident().map(|name| (name, span))
+        })?;
+        let mut args: Vec<String> = vec![];
+        for (name, span) in params {
+            // the function is otherwise fine, so keep parsing it.
+            if args.contains(&name) {
+                self.errors
+                    .push(ParseError::DuplicateParameter { name, span });
             } else {
-                panic!(
-                    "parse error: illegal token for args of function defition {:?}",
-                    t
-                )
+                args.push(name);
             }
-            match self.peek() {
-                Some(Token::RParen) => break,
-                Some(Token::Comma) => self.get(),
-                _ => panic!("parse error: unexpected token {:?}"),
-            };
         }
-        self.get();
+        // a function body starts outside of any loop.
+        let loop_depth = self.loop_depth;
+        self.loop_depth = 0;
+        let stmts = self.block();
+        self.loop_depth = loop_depth;
+        Ok(AST::fn_def(args, stmts?).with_span(start.to(self.prev_span())))
+    }
 
-        if let ASTKind::Compound(stmts) = self.compound_statement().kind {
-            AST::fn_def(args, stmts)
-        } else {
-            panic!("")
-        }
+    fn expression(&mut self) -> Result<AST, ParseError> {
+        self.expression_bp(Precedence::Lowest)
     }
 
-    fn relational(&mut self) -> AST {
-        let mut left = self.additive();
-        loop {
-            match self.peek() {
-                Some(Token::LT) | Some(Token::GT) => {
-                    let op = self.get().unwrap();
-                    let right = self.primary();
-                    left = AST::binary(op, left, right);
+    // parses an expression whose binary operators all bind tighter than
+    // `min`. Operators of equal precedence stop the loop of the right operand,
+    // so they associate to the left.
+    fn expression_bp(&mut self, min: Precedence) -> Result<AST, ParseError> {
+        let left = self.prefix()?;
+        self.infix(left, min)
+    }
+
+    // parses the binary operators, calls and indexing that follow `left`
+    // and bind tighter than `min`.
+    fn infix(&mut self, mut left: AST, min: Precedence) -> Result<AST, ParseError> {
+        while let Some((prec, op)) = self.peek().as_ref().and_then(infix_operator) {
+            if prec <= min {
+                break;
+            }
+            left = match op {
+                Infix::Call => self.call(left)?,
+                Infix::Index => self.index(left)?,
+                Infix::Binary(build) => {
+                    self.get();
+                    let right = self.expression_bp(prec)?;
+                    let span = left.span.to(right.span);
+                    build(left, right).with_span(span)
                 }
-                _ => break,
             };
         }
-        left
+        Ok(left)
     }
 
-    fn expression(&mut self) -> AST {
-        match self.peek() {
-            Some(Token::Function) => self.fn_def(),
-            Some(_) => self.relational(),
-            _ => unimplemented!(),
+    // parses the argument list of a call to `callee`.
+    fn call(&mut self, callee: AST) -> Result<AST, ParseError> {
+        let start = callee.span;
+        self.expect(Token::LParen)?;
+        let args = self.comma_separated(Token::RParen, |p| p.expression())?;
+        Ok(AST::fn_call(callee, args).with_span(start.to(self.prev_span())))
+    }
+
+    // parses the `[index]` following `left`.
+    fn index(&mut self, left: AST) -> Result<AST, ParseError> {
+        let start = left.span;
+        self.expect(Token::LBracket)?;
+        let index = self.expression()?;
+        self.expect(Token::RBracket)?;
+        Ok(AST::index(left, index).with_span(start.to(self.prev_span())))
+    }
+
+    // parses `item, item, ...` up to and including `close`, once the opening
+    // token has been consumed. The list may be empty and may end with a
+    // trailing comma.
+    fn comma_separated<T, F>(&mut self, close: Token, mut item: F) -> Result<Vec<T>, ParseError>
+    where
+        F: FnMut(&mut Self) -> Result<T, ParseError>,
+    {
+        let mut items = vec![];
+        while self.peek().as_ref() != Some(&close) {
+            items.push(item(self)?);
+            if self.peek() != Some(Token::Comma) {
+                break;
+            }
+            self.get();
         }
+        self.expect(close)?;
+        Ok(items)
     }
 
-    fn expression_statement(&mut self) -> AST {
-        let ast = self.expression();
-        assert_eq!(self.get(), Some(Token::Semicolon));
-        ast
+    fn expression_statement(&mut self) -> Result<AST, ParseError> {
+        let ast = self.expression()?;
+        self.expect(Token::Semicolon)?;
+        Ok(ast)
     }
 
-    fn statement(&mut self) -> AST {
+    fn statement(&mut self) -> Result<AST, ParseError> {
         match self.peek() {
             Some(Token::Let) => self.let_stmt(),
+            Some(Token::Ident(_)) if self.peek_nth(1) == Some(Token::Assign) => self.assign_stmt(),
             Some(Token::Return) => self.return_stmt(),
-            Some(Token::LBrace) => self.compound_statement(),
+            Some(Token::LBrace) => self.brace_statement(),
             Some(Token::If) => self.if_stmt(),
             Some(Token::While) => self.while_stmt(),
-            Some(_) => self.expression_statement(),
-            None => panic!("parse error: try to parse statement but got None"),
+            Some(Token::Break) | Some(Token::Continue) => self.loop_control(),
+            _ => self.expression_statement(),
         }
     }
 
-    pub fn parse(&mut self) {
-        while self.peek() != Some(Token::EOF) {
-            let node = self.statement();
-            self.result.push(node);
+    /// Parses the whole token stream. On failure every error found is
+    /// returned, in source order, rather than only the first one.
+    pub fn parse(&mut self) -> Result<Vec<AST>, Vec<ParseError>> {
+        let mut stmts = vec![];
+        while let Some(t) = self.peek() {
+            if t == Token::EOF {
+                break;
+            }
+            let index = self.index;
+            match self.statement() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(e) => {
+                    self.report(e, index);
+                    self.synchronize(index);
+                }
+            }
+        }
+        let lex_errors = self.lex_errors.drain(..).map(|(_, e)| ParseError::Lex(e));
+        self.errors.extend(lex_errors);
+        if self.errors.is_empty() {
+            Ok(stmts)
+        } else {
+            let mut errors = std::mem::take(&mut self.errors);
+            errors.sort_by_key(|e| e.span().start);
+            Err(errors)
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Parser, Token, AST};
+    use super::{ASTKind, ParseError, Parser, Token, AST};
+    use lexer::{tokenize, LexErrorKind, Lexer};
+    use span::Span;
+    use token::SpannedToken;
+
+    fn parser(tokens: &[SpannedToken]) -> Parser<'_> {
+        Parser::new(tokens.iter().cloned().map(Ok))
+    }
+
+    fn spanned(tokens: Vec<Token>) -> Vec<SpannedToken> {
+        tokens
+            .into_iter()
+            .map(|t| SpannedToken::new(t, Span::default()))
+            .collect()
+    }
+
     #[test]
     fn parse_one_plus_two() {
-        let tokens: [Token; 4] = [Token::Int(1), Token::Plus, Token::Int(2), Token::EOF];
-        let mut p = Parser::new(&tokens);
-        assert_eq!(p.additive(), AST::add(AST::int(1), AST::int(2)))
+        let tokens = spanned(vec![Token::Int(1), Token::Plus, Token::Int(2), Token::EOF]);
+        let mut p = parser(&tokens);
+        assert_eq!(p.expression(), Ok(AST::add(AST::int(1), AST::int(2))))
     }
 
     #[test]
     fn parse_one_plus_two_plus_three() {
-        let t = vec![
+        let t = spanned(vec![
             Token::Int(1),
             Token::Plus,
             Token::Int(2),
             Token::Plus,
             Token::Int(3),
             Token::EOF,
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
-            p.additive(),
-            AST::add(AST::add(AST::int(1), AST::int(2)), AST::int(3))
+            p.expression(),
+            Ok(AST::add(AST::add(AST::int(1), AST::int(2)), AST::int(3)))
         )
     }
 
     #[test]
     fn parse_one_times_two() {
-        let t = vec![Token::Int(1), Token::Star, Token::Int(2), Token::EOF];
-        let mut p = Parser::new(&t);
-        assert_eq!(p.multiplicative(), AST::multi(AST::int(1), AST::int(2)))
+        let t = spanned(vec![Token::Int(1), Token::Star, Token::Int(2), Token::EOF]);
+        let mut p = parser(&t);
+        assert_eq!(p.expression(), Ok(AST::multi(AST::int(1), AST::int(2))))
     }
 
     #[test]
     fn parse_one_plus_two_times_three() {
-        let t = vec![
+        let t = spanned(vec![
             Token::Int(1),
             Token::Plus,
             Token::Int(2),
             Token::Star,
             Token::Int(3),
             Token::EOF,
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
-            p.additive(),
-            AST::add(AST::int(1), AST::multi(AST::int(2), AST::int(3)))
+            p.expression(),
+            Ok(AST::add(AST::int(1), AST::multi(AST::int(2), AST::int(3))))
         )
     }
 
     #[test]
     fn parse_one_plus_two_times_three_plus_four() {
-        let t = vec![
+        let t = spanned(vec![
             Token::Int(1),
             Token::Plus,
             Token::Int(2),
@@ -454,29 +1078,29 @@ mod tests {
             Token::Int(3),
             Token::Plus,
             Token::Int(4),
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
-            p.additive(),
-            AST::add(
+            p.expression(),
+            Ok(AST::add(
                 AST::add(AST::int(1), AST::multi(AST::int(2), AST::int(3))),
                 AST::int(4)
-            )
+            ))
         )
     }
 
     #[test]
     fn test_peek() {
-        let tokens: [Token; 4] = [Token::Int(1), Token::Plus, Token::Int(2), Token::EOF];
-        let p = Parser::new(&tokens);
+        let tokens = spanned(vec![Token::Int(1), Token::Plus, Token::Int(2), Token::EOF]);
+        let mut p = parser(&tokens);
         assert_eq!(p.peek(), Some(Token::Int(1)));
         assert_eq!(p.index, 0);
     }
 
     #[test]
     fn test_get() {
-        let tokens: [Token; 4] = [Token::Int(1), Token::Plus, Token::Int(2), Token::EOF];
-        let mut p = Parser::new(&tokens);
+        let tokens = spanned(vec![Token::Int(1), Token::Plus, Token::Int(2), Token::EOF]);
+        let mut p = parser(&tokens);
         assert_eq!(p.get(), Some(Token::Int(1)));
         assert_eq!(p.get(), Some(Token::Plus));
         assert_eq!(p.index, 2);
@@ -484,17 +1108,20 @@ mod tests {
 
     #[test]
     fn test_let_stmt() {
-        let t = vec![
+        let t = spanned(vec![
             Token::Let,
             Token::Ident("x".to_string()),
             Token::Assign,
             Token::Int(10),
             Token::Semicolon,
-        ];
-        let mut p = Parser::new(&t);
-        assert_eq!(p.let_stmt(), AST::let_stmt("x".to_string(), AST::int(10)));
+        ]);
+        let mut p = parser(&t);
+        assert_eq!(
+            p.let_stmt(),
+            Ok(AST::let_stmt("x".to_string(), AST::int(10)))
+        );
 
-        let t = vec![
+        let t = spanned(vec![
             Token::Let,
             Token::Ident("x".to_string()),
             Token::Assign,
@@ -502,33 +1129,39 @@ mod tests {
             Token::Plus,
             Token::Int(20),
             Token::Semicolon,
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
             p.let_stmt(),
-            AST::let_stmt("x".to_string(), AST::add(AST::int(10), AST::int(20)))
+            Ok(AST::let_stmt(
+                "x".to_string(),
+                AST::add(AST::int(10), AST::int(20))
+            ))
         );
     }
 
     #[test]
     fn parse_return_stmt() {
-        let t = vec![
+        let t = spanned(vec![
             Token::Return,
             Token::Ident("x".to_string()),
             Token::Plus,
             Token::Int(1),
             Token::Semicolon,
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
             p.return_stmt(),
-            AST::return_stmt(AST::add(AST::ident("x".to_string()), AST::int(1)))
+            Ok(AST::return_stmt(AST::add(
+                AST::ident("x".to_string()),
+                AST::int(1)
+            )))
         )
     }
 
     #[test]
     fn parse_some_statements() {
-        let t = vec![
+        let t = spanned(vec![
             Token::Int(1),
             Token::Plus,
             Token::Int(2),
@@ -538,21 +1171,20 @@ mod tests {
             Token::Int(4),
             Token::Semicolon,
             Token::EOF,
-        ];
-        let mut p = Parser::new(&t);
-        p.parse();
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
-            p.result,
-            vec![
+            p.parse(),
+            Ok(vec![
                 AST::add(AST::int(1), AST::int(2)),
                 AST::multi(AST::int(3), AST::int(4))
-            ]
+            ])
         );
     }
 
     #[test]
     fn parse_compound() {
-        let t = vec![
+        let t = spanned(vec![
             Token::LBrace,
             Token::Int(1),
             Token::Plus,
@@ -564,37 +1196,41 @@ mod tests {
             Token::Semicolon,
             Token::RBrace,
             Token::EOF,
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
-            p.compound_statement(),
-            AST::compound_statement(vec![
+            p.statement(),
+            Ok(AST::compound_statement(vec![
                 AST::add(AST::int(1), AST::int(2)),
                 AST::multi(AST::int(3), AST::int(4))
-            ])
+            ]))
         );
     }
 
     #[test]
     fn parse_if_stmt() {
-        let t = vec![
+        let t = spanned(vec![
             Token::If,
             Token::Int(1),
             Token::Return,
             Token::Int(10),
             Token::Semicolon,
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
             p.if_stmt(),
-            AST::if_stmt(AST::int(1), AST::return_stmt(AST::int(10)), None)
+            Ok(AST::if_stmt(
+                AST::int(1),
+                AST::return_stmt(AST::int(10)),
+                None
+            ))
         );
 
         // if 1 {
         //     1;
         //     2;
         // }
-        let t = vec![
+        let t = spanned(vec![
             Token::If,
             Token::Int(1),
             Token::LBrace,
@@ -603,18 +1239,18 @@ mod tests {
             Token::Int(2),
             Token::Semicolon,
             Token::RBrace,
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
             p.if_stmt(),
-            AST::if_stmt(
+            Ok(AST::if_stmt(
                 AST::int(1),
                 AST::compound_statement(vec![AST::int(1), AST::int(2)]),
                 None
-            )
+            ))
         );
 
-        let t = vec![
+        let t = spanned(vec![
             Token::If,
             Token::Int(1),
             Token::Return,
@@ -625,33 +1261,33 @@ mod tests {
             Token::Int(20),
             Token::Semicolon,
             Token::RBrace,
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
             p.if_stmt(),
-            AST::if_stmt(
+            Ok(AST::if_stmt(
                 AST::int(1),
                 AST::return_stmt(AST::int(10)),
                 Some(AST::return_stmt(AST::int(20)))
-            )
+            ))
         );
     }
 
     #[test]
     fn parse_fncall() {
-        let t = vec![
+        let t = spanned(vec![
             Token::Ident("x".to_string()),
             Token::LParen,
             Token::Int(1),
             Token::RParen,
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
-            p.primary(),
-            AST::fn_call("x".to_string(), vec![AST::int(1)])
+            p.expression(),
+            Ok(AST::fn_call(AST::ident("x".to_string()), vec![AST::int(1)]))
         );
 
-        let t = vec![
+        let t = spanned(vec![
             Token::Ident("x".to_string()),
             Token::LParen,
             Token::Int(1),
@@ -660,20 +1296,20 @@ mod tests {
             Token::Comma,
             Token::Int(3),
             Token::RParen,
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
-            p.primary(),
-            AST::fn_call(
-                "x".to_string(),
+            p.expression(),
+            Ok(AST::fn_call(
+                AST::ident("x".to_string()),
                 vec![AST::add(AST::int(1), AST::int(2)), AST::int(3)]
-            )
+            ))
         );
     }
 
     #[test]
     fn parse_fndef() {
-        let t = vec![
+        let t = spanned(vec![
             Token::Function,
             Token::LParen,
             Token::Ident("x".to_string()),
@@ -687,40 +1323,45 @@ mod tests {
             Token::Ident("y".to_string()),
             Token::Semicolon,
             Token::RBrace,
-        ];
-        let mut p = Parser::new(&t);
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
             p.fn_def(),
-            AST::fn_def(
+            Ok(AST::fn_def(
                 vec!["x".to_string(), "y".to_string()],
                 vec![AST::return_stmt(AST::add(
                     AST::ident("x".to_string()),
                     AST::ident("y".to_string())
                 ))]
-            )
+            ))
         )
     }
 
     #[test]
     fn parse_while_stmt() {
-        let t = vec![Token::While, Token::True, Token::Int(1), Token::Semicolon];
-        let mut p = Parser::new(&t);
+        let t = spanned(vec![
+            Token::While,
+            Token::True,
+            Token::Int(1),
+            Token::Semicolon,
+        ]);
+        let mut p = parser(&t);
         assert_eq!(
             p.while_stmt(),
-            AST::while_stmt(AST::bool(true), AST::int(1))
+            Ok(AST::while_stmt(AST::bool(true), AST::int(1)))
         )
     }
 
     #[test]
     fn parse_relational() {
-        let t = vec![Token::Int(1), Token::LT, Token::Int(2)];
-        let mut p = Parser::new(&t);
-        assert_eq!(p.relational(), AST::lt(AST::int(1), AST::int(2)))
+        let t = spanned(vec![Token::Int(1), Token::LT, Token::Int(2)]);
+        let mut p = parser(&t);
+        assert_eq!(p.expression(), Ok(AST::lt(AST::int(1), AST::int(2))))
     }
 
     #[test]
     fn parse_lrparen() {
-        let t = vec![
+        let t = spanned(vec![
             Token::LParen,
             Token::Int(1),
             Token::Plus,
@@ -728,11 +1369,518 @@ mod tests {
             Token::RParen,
             Token::Star,
             Token::Int(3),
+        ]);
+        let mut p = parser(&t);
+        assert_eq!(
+            p.expression(),
+            Ok(AST::multi(AST::add(AST::int(1), AST::int(2)), AST::int(3)))
+        );
+    }
+
+    fn lex(src: &str) -> Vec<SpannedToken> {
+        tokenize(src).unwrap()
+    }
+
+    #[test]
+    fn parse_assignment() {
+        let t = lex("x = 1 + 2; { y = x; } x == 1;");
+        let mut p = parser(&t);
+        let id = |s: &str| AST::ident(s.to_string());
+        assert_eq!(
+            p.parse(),
+            Ok(vec![
+                AST::assign_stmt("x".to_string(), AST::add(AST::int(1), AST::int(2))),
+                AST::compound_statement(vec![AST::assign_stmt("y".to_string(), id("x"))]),
+                AST::eq(id("x"), AST::int(1)),
+            ])
+        );
+
+        let t = lex("x = ;");
+        let mut p = parser(&t);
+        assert_eq!(p.parse().unwrap_err()[0].to_string(), "unexpected `;`");
+    }
+
+    #[test]
+    fn parse_spans() {
+        let t = lex("let x = 1 +\n  23;");
+        let mut p = parser(&t);
+        let stmt = &p.parse().unwrap()[0];
+        assert_eq!((stmt.span.start.offset, stmt.span.end.offset), (0, 17));
+        if let ASTKind::Let { ref expr, .. } = stmt.kind {
+            assert_eq!((expr.span.start.line, expr.span.start.column), (1, 9));
+            assert_eq!((expr.span.end.line, expr.span.end.column), (2, 5));
+        } else {
+            panic!("expected let statement, got {:?}", stmt);
+        }
+    }
+
+    #[test]
+    fn parse_errors_are_collected() {
+        let t = lex("let = 5;\nlet y = ;\n1 + 2;\nlet z = 1");
+        let mut p = parser(&t);
+        let errors = p.parse().unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(
+            errors[0],
+            ParseError::ExpectedIdent {
+                found: Token::Assign,
+                span: t[1].span,
+            }
+        );
+        assert_eq!(
+            errors[1],
+            ParseError::UnexpectedToken {
+                found: Token::Semicolon,
+                span: t[7].span,
+            }
+        );
+        assert_eq!(
+            errors[2],
+            ParseError::Expected {
+                expected: Token::Semicolon,
+                found: Token::EOF,
+                span: Span::new(t[15].span.end, t[15].span.end),
+            }
+        );
+        assert_eq!(errors[2].to_string(), "expected `;`, found end of input");
+    }
+
+    #[test]
+    fn parse_error_recovery_in_block() {
+        let t = lex("if true { 1 +; x; ) }\nlet 1 = 2;");
+        let mut p = parser(&t);
+        let errors = p.parse().unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec![
+                "unexpected `;`",
+                "unexpected `)`",
+                "expected identifier, found `1`",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_unterminated_block() {
+        let t = lex("while true {\n  1;\n");
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse(),
+            Err(vec![ParseError::UnterminatedBlock { span: t[2].span }])
+        );
+    }
+
+    #[test]
+    fn parse_break_continue() {
+        let t = lex("while true { break; continue; }");
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse(),
+            Ok(vec![AST::while_stmt(
+                AST::bool(true),
+                AST::compound_statement(vec![AST::break_stmt(), AST::continue_stmt()])
+            )])
+        );
+
+        let t = lex("break;\nwhile true { let f = fn(x) { continue; }; }");
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse(),
+            Err(vec![
+                ParseError::OutsideLoop {
+                    keyword: Token::Break,
+                    span: t[0].span,
+                },
+                ParseError::OutsideLoop {
+                    keyword: Token::Continue,
+                    span: t[13].span,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_equality() {
+        // 1 < 2 == 3 >= 4 != true
+        let t = spanned(vec![
+            Token::Int(1),
+            Token::LT,
+            Token::Int(2),
+            Token::Eq,
+            Token::Int(3),
+            Token::GTE,
+            Token::Int(4),
+            Token::NotEq,
+            Token::True,
+        ]);
+        let mut p = parser(&t);
+        assert_eq!(
+            p.expression(),
+            Ok(AST::not_eq(
+                AST::eq(
+                    AST::lt(AST::int(1), AST::int(2)),
+                    AST::gte(AST::int(3), AST::int(4))
+                ),
+                AST::bool(true)
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_unary() {
+        // -1 * !x - -f(2)
+        let t = spanned(vec![
+            Token::Minus,
+            Token::Int(1),
+            Token::Star,
+            Token::Bang,
+            Token::Ident("x".to_string()),
+            Token::Minus,
+            Token::Minus,
+            Token::Ident("f".to_string()),
+            Token::LParen,
+            Token::Int(2),
+            Token::RParen,
+        ]);
+        let mut p = parser(&t);
+        assert_eq!(
+            p.expression(),
+            Ok(AST::minus(
+                AST::multi(AST::neg(AST::int(1)), AST::not(AST::ident("x".to_string()))),
+                AST::neg(AST::fn_call(AST::ident("f".to_string()), vec![AST::int(2)]))
+            ))
+        );
+
+        let t = spanned(vec![Token::Bang, Token::Bang, Token::True]);
+        let mut p = parser(&t);
+        assert_eq!(p.expression(), Ok(AST::not(AST::not(AST::bool(true)))));
+    }
+
+    #[test]
+    fn parse_precedence() {
+        let cases = vec![
+            (
+                "1 < 2 + 3;",
+                AST::lt(AST::int(1), AST::add(AST::int(2), AST::int(3))),
+            ),
+            (
+                "1 - 2 - 3;",
+                AST::minus(AST::minus(AST::int(1), AST::int(2)), AST::int(3)),
+            ),
+            (
+                "1 < 2 < 3;",
+                AST::lt(AST::lt(AST::int(1), AST::int(2)), AST::int(3)),
+            ),
+            (
+                "1 + 2 * 3 == 7 != false;",
+                AST::not_eq(
+                    AST::eq(
+                        AST::add(AST::int(1), AST::multi(AST::int(2), AST::int(3))),
+                        AST::int(7),
+                    ),
+                    AST::bool(false),
+                ),
+            ),
+            ("-1 * 2;", AST::multi(AST::neg(AST::int(1)), AST::int(2))),
+            ("-(1 * 2);", AST::neg(AST::multi(AST::int(1), AST::int(2)))),
+            (
+                "fn(x) { x; } == 1;",
+                AST::eq(
+                    AST::fn_def(vec!["x".to_string()], vec![AST::ident("x".to_string())]),
+                    AST::int(1),
+                ),
+            ),
         ];
-        let mut p = Parser::new(&t);
+        for (src, expected) in cases {
+            let t = lex(src);
+            let mut p = parser(&t);
+            assert_eq!(p.parse(), Ok(vec![expected]), "{}", src);
+        }
+    }
+
+    #[test]
+    fn parse_call_expressions() {
+        let t = lex("f(1)(2); fn(x) { x; }(3); -g(4);");
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse(),
+            Ok(vec![
+                AST::fn_call(
+                    AST::fn_call(AST::ident("f".to_string()), vec![AST::int(1)]),
+                    vec![AST::int(2)]
+                ),
+                AST::fn_call(
+                    AST::fn_def(vec!["x".to_string()], vec![AST::ident("x".to_string())]),
+                    vec![AST::int(3)]
+                ),
+                AST::neg(AST::fn_call(AST::ident("g".to_string()), vec![AST::int(4)])),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_empty_and_trailing_comma_lists() {
+        let t = lex("fn() { 1; }; f(); fn(a, b,) { a; }; f(1, 2,);");
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse(),
+            Ok(vec![
+                AST::fn_def(vec![], vec![AST::int(1)]),
+                AST::fn_call(AST::ident("f".to_string()), vec![]),
+                AST::fn_def(
+                    vec!["a".to_string(), "b".to_string()],
+                    vec![AST::ident("a".to_string())]
+                ),
+                AST::fn_call(AST::ident("f".to_string()), vec![AST::int(1), AST::int(2)]),
+            ])
+        );
+
+        let t = lex("f(1 2); f(,); fn(a,,) { a; };");
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse()
+                .unwrap_err()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "expected `)`, found `2`",
+                "unexpected `,`",
+                "expected identifier, found `,`",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_duplicate_parameter() {
+        let t = lex("let f = fn(a, b, a) { a; };");
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse(),
+            Err(vec![ParseError::DuplicateParameter {
+                name: "a".to_string(),
+                span: t[9].span,
+            }])
+        );
+    }
+
+    #[test]
+    fn parse_comments() {
+        let src = "let x = /* one */ 1; // the first\nx / 2;";
+        let expected = Ok(vec![
+            AST::let_stmt("x".to_string(), AST::int(1)),
+            AST::div(AST::ident("x".to_string()), AST::int(2)),
+        ]);
+        assert_eq!(Parser::new(Lexer::new(src)).parse(), expected);
+        assert_eq!(
+            Parser::new(Lexer::new(src).with_comments()).parse(),
+            expected
+        );
+
+        let errors = Parser::new(Lexer::new("let x = 1; /* /* */"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(errors[0].to_string(), "unterminated block comment");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn parse_strings() {
+        let t = lex(r#"let s = "a" + "b\n";"#);
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse(),
+            Ok(vec![AST::let_stmt(
+                "s".to_string(),
+                AST::add(AST::str("a".to_string()), AST::str("b\n".to_string()))
+            )])
+        );
+
+        // the bad escape is reported once, without cascading parse errors
+        let errors = Parser::new(Lexer::new(r#"let s = "a\qb"; let t = "open"#))
+            .parse()
+            .unwrap_err();
+        let kinds: Vec<_> = errors
+            .iter()
+            .map(|e| match *e {
+                ParseError::Lex(ref e) => e.kind.clone(),
+                ref e => panic!("unexpected error {:?}", e),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                LexErrorKind::InvalidEscape('q'),
+                LexErrorKind::UnterminatedString
+            ]
+        );
+        assert_eq!(errors[0].to_string(), "unknown escape sequence `\\q`");
+    }
+
+    #[test]
+    fn parse_arrays_and_index() {
+        let t = lex("[]; [1, 2 * 3,]; a[1 + 1][0]; f()[0](1); -a[0];");
+        let mut p = parser(&t);
+        let a = || AST::ident("a".to_string());
+        assert_eq!(
+            p.parse(),
+            Ok(vec![
+                AST::array(vec![]),
+                AST::array(vec![AST::int(1), AST::multi(AST::int(2), AST::int(3))]),
+                AST::index(
+                    AST::index(a(), AST::add(AST::int(1), AST::int(1))),
+                    AST::int(0)
+                ),
+                AST::fn_call(
+                    AST::index(
+                        AST::fn_call(AST::ident("f".to_string()), vec![]),
+                        AST::int(0)
+                    ),
+                    vec![AST::int(1)]
+                ),
+                AST::neg(AST::index(a(), AST::int(0))),
+            ])
+        );
+
+        let t = lex("a[1;");
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse().unwrap_err()[0].to_string(),
+            "expected `]`, found `;`"
+        );
+    }
+
+    #[test]
+    fn parse_hashes() {
+        let t = lex(r#"{"a": 1, 2: x + 1,}; {} { x; } let h = {true: {}}; {1: 2}[1];"#);
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse(),
+            Ok(vec![
+                AST::hash(vec![
+                    (AST::str("a".to_string()), AST::int(1)),
+                    (
+                        AST::int(2),
+                        AST::add(AST::ident("x".to_string()), AST::int(1))
+                    ),
+                ]),
+                AST::compound_statement(vec![]),
+                AST::compound_statement(vec![AST::ident("x".to_string())]),
+                AST::let_stmt(
+                    "h".to_string(),
+                    AST::hash(vec![(AST::bool(true), AST::hash(vec![]))])
+                ),
+                AST::index(AST::hash(vec![(AST::int(1), AST::int(2))]), AST::int(1)),
+            ])
+        );
+
+        let t = lex("let h = {1 2};");
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse().unwrap_err()[0].to_string(),
+            "expected `:`, found `2`"
+        );
+    }
+
+    #[test]
+    fn parse_token_window() {
+        // only the tokens around the current one are kept, however long the
+        // input is.
+        let src = "let x = 1; { x = x + 1; } {1: x}[1];\n".repeat(1000);
+        let mut p = Parser::new(Lexer::new(&src));
+        assert_eq!(p.parse().map(|stmts| stmts.len()), Ok(3000));
+        assert!(p.tokens.len() <= 3);
+
+        let t = lex("{ x = 1; } y;");
+        let mut p = parser(&t);
+        p.parse().unwrap();
+        assert_eq!(p.prev_span(), t[7].span);
+        assert_eq!(p.peek_span(), Span::new(t[7].span.end, t[7].span.end));
+    }
+
+    #[test]
+    fn parse_nested_blocks() {
+        // each `{` is parsed once whether it opens a block or a hash, so
+        // deep nesting takes linear time.
+        let depth = 24;
+        let src = "{ fn() { ".repeat(depth) + "{1: 2}[1];" + &" }; }".repeat(depth);
+        let stmts = Parser::new(Lexer::new(&src)).parse().unwrap();
+        assert_eq!(stmts.len(), 1);
+
+        // an error in the first statement of a block leaves the rest checked
+        let errors = Parser::new(Lexer::new("{ 1 +; 2 3; }"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec!["unexpected `;`", "expected `;`, found `3`"]
+        );
+    }
+
+    #[test]
+    fn parse_min_integer() {
+        let t = lex("-9223372036854775808; -9223372036854775808 * 2; --9223372036854775808;");
+        let min = || AST::int(i64::MIN);
+        assert_eq!(
+            parser(&t).parse(),
+            Ok(vec![min(), AST::multi(min(), AST::int(2)), AST::neg(min())])
+        );
+        assert_eq!(t[0].span.to(t[1].span), parser(&t).parse().unwrap()[0].span);
+
+        // the magnitude alone is too large, unless integers can be big.
+        let result = Parser::new(Lexer::new("9223372036854775808;")).parse();
+        #[cfg(not(feature = "bigint"))]
+        assert_eq!(
+            result.unwrap_err()[0].to_string(),
+            "integer literal is too large"
+        );
+        #[cfg(feature = "bigint")]
+        assert_eq!(
+            result,
+            Ok(vec![AST::bigint("9223372036854775808".parse().unwrap())])
+        );
+    }
+
+    #[test]
+    fn parse_division() {
+        let t = lex("1 + 6 / 3 % 2 * 4;");
+        let mut p = parser(&t);
+        assert_eq!(
+            p.parse(),
+            Ok(vec![AST::add(
+                AST::int(1),
+                AST::multi(
+                    AST::modulo(AST::div(AST::int(6), AST::int(3)), AST::int(2)),
+                    AST::int(4)
+                )
+            )])
+        );
+    }
+
+    #[test]
+    fn parse_floats() {
+        let t = lex("let x = -1.5 * 2e3;");
+        let mut p = parser(&t);
         assert_eq!(
-            p.additive(),
-            AST::multi(AST::add(AST::int(1), AST::int(2)), AST::int(3))
+            p.parse(),
+            Ok(vec![AST::let_stmt(
+                "x".to_string(),
+                AST::multi(AST::neg(AST::float(1.5)), AST::float(2e3))
+            )])
+        );
+    }
+
+    #[test]
+    fn parse_logical_operators() {
+        let t = lex("a || b && c == d; a && b || !c;");
+        let mut p = parser(&t);
+        let id = |s: &str| AST::ident(s.to_string());
+        assert_eq!(
+            p.parse(),
+            Ok(vec![
+                AST::or(id("a"), AST::and(id("b"), AST::eq(id("c"), id("d")))),
+                AST::or(AST::and(id("a"), id("b")), AST::not(id("c"))),
+            ])
         );
     }
 }
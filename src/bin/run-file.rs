@@ -0,0 +1,32 @@
+extern crate monkey_rs;
+
+use monkey_rs::repl;
+use std::env;
+use std::process;
+
+fn main() {
+    let mut no_color = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        if arg == "--no-color" {
+            no_color = true;
+        } else {
+            path = Some(arg);
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: run-file [--no-color] <file>");
+            process::exit(2);
+        }
+    };
+    match repl::run_file(&path, repl::color_enabled(no_color)) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(2);
+        }
+    }
+}
@@ -1,7 +1,9 @@
 extern crate monkey_rs;
 
 use monkey_rs::repl;
+use std::env;
 
 fn main() {
-    repl::start();
+    let no_color = env::args().skip(1).any(|a| a == "--no-color");
+    repl::start(repl::color_enabled(no_color));
 }
@@ -0,0 +1,148 @@
+use eval::{Object, RuntimeError};
+#[cfg(feature = "bigint")]
+use num_bigint::BigInt;
+#[cfg(feature = "bigint")]
+use num_traits::FromPrimitive;
+use span::Span;
+use std::rc::Rc;
+
+/// The functions every program can call without defining them. A `let` of
+/// the same name shadows the builtin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Builtin {
+    /// The number of elements of an array or hash, or of characters of a
+    /// string.
+    Len,
+    /// The first element of an array, or null if it is empty.
+    First,
+    /// The last element of an array, or null if it is empty.
+    Last,
+    /// A new array of all but the first element, or null if it is empty.
+    Rest,
+    /// A new array with the second argument appended to the first.
+    Push,
+    /// The largest integer not greater than a number.
+    Floor,
+    /// The smallest integer not less than a number.
+    Ceil,
+    /// The integer nearest to a number, rounding halves away from zero.
+    Round,
+    /// The square root of a number, as a float.
+    Sqrt,
+    /// A number converted to an integer, rounding toward zero.
+    Int,
+    /// A number converted to a float.
+    Float,
+}
+
+impl Builtin {
+    pub fn lookup(name: &str) -> Option<Builtin> {
+        match name {
+            "len" => Some(Builtin::Len),
+            "first" => Some(Builtin::First),
+            "last" => Some(Builtin::Last),
+            "rest" => Some(Builtin::Rest),
+            "push" => Some(Builtin::Push),
+            "floor" => Some(Builtin::Floor),
+            "ceil" => Some(Builtin::Ceil),
+            "round" => Some(Builtin::Round),
+            "sqrt" => Some(Builtin::Sqrt),
+            "int" => Some(Builtin::Int),
+            "float" => Some(Builtin::Float),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Len => "len",
+            Builtin::First => "first",
+            Builtin::Last => "last",
+            Builtin::Rest => "rest",
+            Builtin::Push => "push",
+            Builtin::Floor => "floor",
+            Builtin::Ceil => "ceil",
+            Builtin::Round => "round",
+            Builtin::Sqrt => "sqrt",
+            Builtin::Int => "int",
+            Builtin::Float => "float",
+        }
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Builtin::Push => 2,
+            _ => 1,
+        }
+    }
+
+    /// Applies the builtin to `args`; `span` is the whole call.
+    pub fn call(self, args: Vec<Object>, span: Span) -> Result<Object, RuntimeError> {
+        if args.len() != self.arity() {
+            return Err(RuntimeError::ArityMismatch {
+                expected: self.arity(),
+                found: args.len(),
+                span,
+            });
+        }
+        let mut args = args.into_iter();
+        let arg = args.next().unwrap();
+        let result = match (self, &arg) {
+            (Builtin::Len, Object::Array(a)) => Object::Integer(a.len() as i64),
+            (Builtin::Len, Object::Hash(h)) => Object::Integer(h.len() as i64),
+            (Builtin::Len, Object::Str(s)) => Object::Integer(s.chars().count() as i64),
+            (Builtin::First, Object::Array(a)) => a.first().cloned().unwrap_or(Object::Null),
+            (Builtin::Last, Object::Array(a)) => a.last().cloned().unwrap_or(Object::Null),
+            (Builtin::Rest, Object::Array(a)) if a.is_empty() => Object::Null,
+            (Builtin::Rest, Object::Array(a)) => Object::Array(Rc::new(a[1..].to_vec())),
+            (Builtin::Push, Object::Array(a)) => {
+                let mut a = a.as_ref().clone();
+                a.push(args.next().unwrap());
+                Object::Array(Rc::new(a))
+            }
+            (
+                Builtin::Floor | Builtin::Ceil | Builtin::Round | Builtin::Int,
+                Object::Integer(_),
+            ) => arg,
+            #[cfg(feature = "bigint")]
+            (Builtin::Floor | Builtin::Ceil | Builtin::Round | Builtin::Int, Object::BigInt(_)) => {
+                arg
+            }
+            (Builtin::Floor, Object::Float(x)) => return float_to_int(x.floor(), span),
+            (Builtin::Ceil, Object::Float(x)) => return float_to_int(x.ceil(), span),
+            (Builtin::Round, Object::Float(x)) => return float_to_int(x.round(), span),
+            (Builtin::Int, Object::Float(x)) => return float_to_int(x.trunc(), span),
+            (Builtin::Sqrt, _) if arg.as_float().is_some() => {
+                Object::Float(arg.as_float().unwrap().sqrt())
+            }
+            (Builtin::Float, _) if arg.as_float().is_some() => {
+                Object::Float(arg.as_float().unwrap())
+            }
+            _ => {
+                return Err(RuntimeError::TypeError {
+                    op: self.name(),
+                    operands: vec![arg.type_name()],
+                    span,
+                })
+            }
+        };
+        Ok(result)
+    }
+}
+
+// the integer `x`, which has no fractional part. Infinities and NaN are an
+// error, and so are values out of the range of `i64` unless the `bigint`
+// feature is on.
+fn float_to_int(x: f64, span: Span) -> Result<Object, RuntimeError> {
+    if !x.is_finite() {
+        return Err(RuntimeError::FloatToInt { value: x, span });
+    }
+    // `i64::MIN` is a power of two, so the range check is exact.
+    if x >= i64::MIN as f64 && x < -(i64::MIN as f64) {
+        return Ok(Object::Integer(x as i64));
+    }
+    #[cfg(feature = "bigint")]
+    return Ok(Object::from(BigInt::from_f64(x).unwrap()));
+    #[cfg(not(feature = "bigint"))]
+    Err(RuntimeError::IntegerOverflow { span })
+}
@@ -30,7 +30,7 @@ fn gen_code(tree: AST) {
 
 #[cfg(test)]
 mod tests {
-    use super::{gen_code, ASTKind, AST};
+    use super::{gen_code, AST};
     #[test]
     fn simple_tree() {
         // gen_code(AST {
@@ -84,29 +84,9 @@ mod tests {
         //     ),
         // });
 
-        gen_code(AST {
-            kind: ASTKind::Add(
-                Box::new(AST {
-                    kind: ASTKind::Add(
-                        Box::new(AST {
-                            kind: ASTKind::Int(1),
-                        }),
-                        Box::new(AST {
-                            kind: ASTKind::Multi(
-                                Box::new(AST {
-                                    kind: ASTKind::Int(2),
-                                }),
-                                Box::new(AST {
-                                    kind: ASTKind::Int(3),
-                                }),
-                            ),
-                        }),
-                    ),
-                }),
-                Box::new(AST {
-                    kind: ASTKind::Int(4),
-                }),
-            ),
-        });
+        gen_code(AST::add(
+            AST::add(AST::int(1), AST::multi(AST::int(2), AST::int(3))),
+            AST::int(4),
+        ));
     }
 }
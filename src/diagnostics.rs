@@ -0,0 +1,373 @@
+use ast::ParseError;
+use eval::RuntimeError;
+use lexer::{radix_name, LexError, LexErrorKind};
+use span::Span;
+use std::fmt::Write;
+use token::Token;
+use unicode_xid::UnicodeXID;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// the number of spaces a tab in the source line is echoed as, like rustc.
+const TAB_WIDTH: usize = 4;
+
+// the width of `chars` as echoed, with tabs expanded.
+fn display_width<I: Iterator<Item = char>>(chars: I) -> usize {
+    chars.map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum()
+}
+
+/// An error message pointing at a span of the source, rendered in the style
+/// of rustc:
+///
+/// ```text
+/// error: expected `;`, found end of input
+///  --> main.monkey:1:10
+///   |
+/// 1 | let x = 1
+///   |          ^ expected `;` here
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: String, span: Span) -> Diagnostic {
+        Diagnostic {
+            message,
+            span,
+            label: None,
+            notes: vec![],
+            help: None,
+        }
+    }
+
+    /// Text printed next to the underline.
+    pub fn with_label(mut self, label: String) -> Diagnostic {
+        self.label = Some(label);
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn with_help(mut self, help: String) -> Diagnostic {
+        self.help = Some(help);
+        self
+    }
+
+    /// Renders the diagnostic against `src`, the contents of `file_name`.
+    /// With `color` off the output is plain text, e.g. for CI logs.
+    pub fn render(&self, file_name: &str, src: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+        let start = self.span.start;
+        let line_no = start.line.max(1);
+        let line = src.lines().nth(line_no - 1).unwrap_or("");
+        let gutter = " ".repeat(line_no.to_string().len());
+        let bar = paint(BLUE, "|");
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "{}{}",
+            paint(RED, "error"),
+            paint(BOLD, &format!(": {}", self.message))
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "{}{} {}:{}:{}",
+            gutter,
+            paint(BLUE, "-->"),
+            file_name,
+            line_no,
+            start.column.max(1)
+        )
+        .unwrap();
+        writeln!(out, "{} {}", gutter, bar).unwrap();
+        writeln!(
+            out,
+            "{} {} {}",
+            paint(BLUE, &line_no.to_string()),
+            bar,
+            line.replace('\t', &" ".repeat(TAB_WIDTH))
+        )
+        .unwrap();
+
+        // underline up to the end of the span, or of the line if the span
+        // runs over several lines. Empty spans still get one caret. Columns
+        // count chars, so tabs before and in the span are widened to match
+        // the echoed line.
+        let first = start.column.max(1) - 1;
+        let line_len = line.chars().count();
+        let last = if self.span.end.line == line_no {
+            self.span.end.column.max(1) - 1
+        } else {
+            line_len
+        };
+        let width = display_width(line.chars().skip(first).take(last.saturating_sub(first))).max(1);
+        let indent = display_width(line.chars().take(first)) + first.saturating_sub(line_len);
+        let mut underline = paint(RED, &"^".repeat(width));
+        if let Some(ref label) = self.label {
+            underline = format!("{} {}", underline, paint(RED, label));
+        }
+        writeln!(
+            out,
+            "{} {} {}{}",
+            gutter,
+            bar,
+            " ".repeat(indent),
+            underline
+        )
+        .unwrap();
+
+        if !self.notes.is_empty() || self.help.is_some() {
+            writeln!(out, "{} {}", gutter, bar).unwrap();
+        }
+        for note in &self.notes {
+            writeln!(
+                out,
+                "{} {} {}: {}",
+                gutter,
+                paint(BLUE, "="),
+                paint(BOLD, "note"),
+                note
+            )
+            .unwrap();
+        }
+        if let Some(ref help) = self.help {
+            writeln!(
+                out,
+                "{} {} {}: {}",
+                gutter,
+                paint(BLUE, "="),
+                paint(BOLD, "help"),
+                help
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(e: &ParseError) -> Diagnostic {
+        let d = Diagnostic::error(e.to_string(), e.span());
+        match *e {
+            ParseError::Lex(LexError {
+                kind: LexErrorKind::UnterminatedString,
+                ..
+            }) => d
+                .with_label("this string is never closed".to_string())
+                .with_help("add a `\"` to end the string".to_string()),
+            ParseError::Lex(LexError {
+                kind: LexErrorKind::UnterminatedComment,
+                ..
+            }) => d
+                .with_label("this comment is never closed".to_string())
+                .with_note("block comments nest, so each `/*` needs its own `*/`".to_string()),
+            ParseError::Lex(LexError {
+                kind: LexErrorKind::InvalidEscape(_),
+                ..
+            }) => d.with_label("in this string".to_string()).with_help(
+                "valid escapes are `\\n`, `\\t`, `\\r`, `\\\"`, `\\\\` and `\\u{...}`".to_string(),
+            ),
+            ParseError::Lex(LexError {
+                kind: LexErrorKind::InvalidUnicodeEscape,
+                ..
+            }) => d
+                .with_label("in this string".to_string())
+                .with_help("write a code point as `\\u{1F600}`".to_string()),
+            ParseError::Lex(LexError {
+                kind: LexErrorKind::IntegerTooLarge,
+                ..
+            }) => d
+                .with_label("does not fit in an integer".to_string())
+                .with_note("integers are 64-bit signed".to_string()),
+            ParseError::Lex(LexError {
+                kind: LexErrorKind::MissingDigits { .. },
+                ..
+            }) => d.with_label("expected digits after the prefix".to_string()),
+            ParseError::Lex(LexError {
+                kind: LexErrorKind::InvalidDigit { radix, .. },
+                ..
+            }) => {
+                let digits = match radix {
+                    2 => "`0` and `1`",
+                    8 => "`0` to `7`",
+                    _ => "`0` to `9` and `a` to `f`",
+                };
+                d.with_label("in this literal".to_string())
+                    .with_help(format!("{} digits are {}", radix_name(radix), digits))
+            }
+            ParseError::Lex(LexError {
+                kind: LexErrorKind::FloatTooLarge,
+                ..
+            }) => d.with_label("does not fit in a float".to_string()),
+            ParseError::Lex(LexError {
+                kind: LexErrorKind::IllegalChar(c),
+                ..
+            }) if c.is_xid_continue() => d
+                .with_label("not valid here".to_string())
+                .with_help("this character may appear in an identifier, but not first".to_string()),
+            ParseError::Lex(_) => d.with_label("not valid here".to_string()),
+            ParseError::Expected {
+                expected: Token::Semicolon,
+                ..
+            } => d
+                .with_label("expected `;` here".to_string())
+                .with_help("statements end with a `;`".to_string()),
+            ParseError::Expected { ref expected, .. } => {
+                d.with_label(format!("expected `{}` here", expected))
+            }
+            ParseError::ExpectedIdent { .. } => d.with_label("expected identifier".to_string()),
+            ParseError::UnexpectedToken { .. } => d.with_label("unexpected token".to_string()),
+            ParseError::UnterminatedBlock { .. } => d
+                .with_label("this `{` is never closed".to_string())
+                .with_help("add a `}` to close the block".to_string()),
+            ParseError::OutsideLoop { .. } => {
+                d.with_label("only allowed inside a `while` body".to_string())
+            }
+            ParseError::DuplicateParameter { .. } => {
+                d.with_label("used as a parameter name again here".to_string())
+            }
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(e: &RuntimeError) -> Diagnostic {
+        let d = Diagnostic::error(e.to_string(), e.span());
+        match *e {
+            RuntimeError::UndefinedName { ref name, .. } => d
+                .with_label("not found in this scope".to_string())
+                .with_help(format!("define it first with `let {} = ...;`", name)),
+            RuntimeError::OutsideLoop { .. } => {
+                d.with_label("only allowed inside a `while` body".to_string())
+            }
+            RuntimeError::TypeError { .. } => d,
+            RuntimeError::ArityMismatch { found, .. } => {
+                d.with_label(format!("called with {} argument(s)", found))
+            }
+            RuntimeError::NotCallable { .. } => d.with_label("not a function".to_string()),
+            RuntimeError::IntegerOverflow { .. } => d
+                .with_label("overflowed here".to_string())
+                .with_note("integers are 64-bit signed".to_string()),
+            RuntimeError::FloatToInt { .. } => d.with_label("not a finite number".to_string()),
+            RuntimeError::DivisionByZero { .. } => d.with_label("the divisor is zero".to_string()),
+            RuntimeError::UnhashableKey { .. } => d
+                .with_label("not hashable".to_string())
+                .with_note("hash keys must be integers, bools or strings".to_string()),
+            RuntimeError::RecursionLimit { .. } => d
+                .with_label("this call is too deep".to_string())
+                .with_note("a recursive function needs a case that does not recurse".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Diagnostic;
+    use span::{Position, Span};
+
+    fn span(start: (usize, usize, usize), end: (usize, usize, usize)) -> Span {
+        Span::new(
+            Position {
+                offset: start.0,
+                line: start.1,
+                column: start.2,
+            },
+            Position {
+                offset: end.0,
+                line: end.1,
+                column: end.2,
+            },
+        )
+    }
+
+    #[test]
+    fn render_plain() {
+        let src = "let x = 1;\nx + true;\n";
+        let d = Diagnostic::error(
+            "unsupported operand type for `+`: integer and bool".to_string(),
+            span((11, 2, 1), (19, 2, 9)),
+        )
+        .with_label("this is integer and bool".to_string())
+        .with_note("`+` works on integers".to_string())
+        .with_help("remove the bool".to_string());
+        assert_eq!(
+            d.render("main.monkey", src, false),
+            "\
+error: unsupported operand type for `+`: integer and bool
+ --> main.monkey:2:1
+  |
+2 | x + true;
+  | ^^^^^^^^ this is integer and bool
+  |
+  = note: `+` works on integers
+  = help: remove the bool
+"
+        );
+    }
+
+    #[test]
+    fn render_empty_span_and_color() {
+        let src = "let x = 1";
+        let d = Diagnostic::error("expected `;`".to_string(), span((9, 1, 10), (9, 1, 10)));
+        assert_eq!(
+            d.render("<repl>", src, false),
+            "\
+error: expected `;`
+ --> <repl>:1:10
+  |
+1 | let x = 1
+  |          ^
+"
+        );
+        let colored = d.render("<repl>", src, true);
+        assert!(colored.contains("\x1b[1;31merror\x1b[0m"));
+        assert!(colored.contains("\x1b[1;31m^\x1b[0m"));
+    }
+
+    #[test]
+    fn render_tabs() {
+        // `1 + true` is at columns 10 to 17 of the line, counting the tabs
+        // as one column each.
+        let src = "\tlet x = 1 +\ttrue;\n";
+        let d = Diagnostic::error("type error".to_string(), span((9, 1, 10), (17, 1, 18)));
+        assert_eq!(
+            d.render("f", src, false),
+            "\
+error: type error
+ --> f:1:10
+  |
+1 |     let x = 1 +    true;
+  |             ^^^^^^^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn render_multiline_span() {
+        let src = "if true {\n  1;\n";
+        let d = Diagnostic::error("unclosed `{`".to_string(), span((3, 1, 4), (13, 2, 3)));
+        assert!(d
+            .render("f", src, false)
+            .contains("1 | if true {\n  |    ^^^^^^\n"));
+    }
+}
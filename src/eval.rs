@@ -1,151 +1,872 @@
 use ast::{ASTKind, AST};
-use std::cell::RefCell;
-use std::collections::HashMap;
+use builtins::Builtin;
+#[cfg(feature = "bigint")]
+use num_bigint::{BigInt, Sign};
+#[cfg(feature = "bigint")]
+use num_traits::ToPrimitive;
+use span::Span;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "bigint")]
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::rc::Rc;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum Object {
-    Integer(i32),
+    Integer(i64),
+    /// An integer outside of the range of `Integer`; smaller values are
+    /// always stored as `Integer`, so each number has one representation.
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt),
+    Float(f64),
     Bool(bool),
+    Str(String),
+    /// Arrays are immutable, so copies share their elements.
+    Array(Rc<Vec<Object>>),
+    Hash(Rc<BTreeMap<HashKey, Object>>),
+    /// A closure: the function together with the environment it was defined
+    /// in.
     FnDef {
         args: Vec<String>,
         stmts: Vec<AST>,
-        env: RefCell<Environment>,
+        env: Rc<RefCell<Environment>>,
     },
+    Builtin(Builtin),
     Null,
 }
 
+impl PartialEq for Object {
+    fn eq(&self, other: &Object) -> bool {
+        match (self, other) {
+            (Object::Integer(l), Object::Integer(r)) => l == r,
+            #[cfg(feature = "bigint")]
+            (Object::BigInt(l), Object::BigInt(r)) => l == r,
+            // integers equal floats of the same value.
+            (Object::Float(_), _) | (_, Object::Float(_)) => match float_operands(self, other) {
+                Some((l, r)) => l == r,
+                None => false,
+            },
+            (Object::Bool(l), Object::Bool(r)) => l == r,
+            (Object::Str(l), Object::Str(r)) => l == r,
+            (Object::Array(l), Object::Array(r)) => l == r,
+            (Object::Hash(l), Object::Hash(r)) => l == r,
+            // environments can contain the closure itself, so they are
+            // compared by identity.
+            (
+                Object::FnDef {
+                    args: l_args,
+                    stmts: l_stmts,
+                    env: l_env,
+                },
+                Object::FnDef {
+                    args: r_args,
+                    stmts: r_stmts,
+                    env: r_env,
+                },
+            ) => l_args == r_args && l_stmts == r_stmts && Rc::ptr_eq(l_env, r_env),
+            (Object::Builtin(l), Object::Builtin(r)) => l == r,
+            (Object::Null, Object::Null) => true,
+            _ => false,
+        }
+    }
+}
+
 impl Object {
-    fn func(args: Vec<String>, stmts: Vec<AST>) -> Self {
-        Object::FnDef {
-            args,
-            stmts,
-            env: RefCell::new(Environment::new()),
+    fn func(args: Vec<String>, stmts: Vec<AST>, env: Rc<RefCell<Environment>>) -> Self {
+        Object::FnDef { args, stmts, env }
+    }
+
+    /// The value of a number as a float; integers too large to be exact
+    /// are rounded.
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            Object::Integer(i) => Some(i as f64),
+            #[cfg(feature = "bigint")]
+            Object::BigInt(ref b) => b.to_f64(),
+            Object::Float(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Object::Integer(_) => "integer",
+            #[cfg(feature = "bigint")]
+            Object::BigInt(_) => "integer",
+            Object::Float(_) => "float",
+            Object::Bool(_) => "bool",
+            Object::Str(_) => "string",
+            Object::Array(_) => "array",
+            Object::Hash(_) => "hash",
+            Object::FnDef { .. } | Object::Builtin(_) => "function",
+            Object::Null => "null",
+        }
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl From<BigInt> for Object {
+    /// An `Integer` if `b` fits in one.
+    fn from(b: BigInt) -> Object {
+        match i64::try_from(&b) {
+            Ok(i) => Object::Integer(i),
+            Err(_) => Object::BigInt(b),
+        }
+    }
+}
+
+/// The values that can be used as hash keys.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum HashKey {
+    Integer(i64),
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt),
+    Bool(bool),
+    Str(String),
+}
+
+impl HashKey {
+    /// The key for `obj`, or `None` if it is not hashable.
+    pub fn from_object(obj: &Object) -> Option<HashKey> {
+        match *obj {
+            Object::Integer(i) => Some(HashKey::Integer(i)),
+            #[cfg(feature = "bigint")]
+            Object::BigInt(ref b) => Some(HashKey::BigInt(b.clone())),
+            Object::Bool(b) => Some(HashKey::Bool(b)),
+            Object::Str(ref s) => Some(HashKey::Str(s.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn to_object(&self) -> Object {
+        match *self {
+            HashKey::Integer(i) => Object::Integer(i),
+            #[cfg(feature = "bigint")]
+            HashKey::BigInt(ref b) => Object::BigInt(b.clone()),
+            HashKey::Bool(b) => Object::Bool(b),
+            HashKey::Str(ref s) => Object::Str(s.clone()),
+        }
+    }
+}
+
+// writes `obj` as an element of an array or hash, where strings are quoted.
+fn fmt_element(obj: &Object, f: &mut fmt::Formatter) -> fmt::Result {
+    match *obj {
+        Object::Str(ref s) => write!(f, "{:?}", s),
+        ref obj => write!(f, "{}", obj),
+    }
+}
+
+/// How values are shown to the user: strings without quotes, except inside
+/// arrays and hashes, and functions by their parameters only.
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Object::Integer(i) => write!(f, "{}", i),
+            #[cfg(feature = "bigint")]
+            Object::BigInt(ref b) => write!(f, "{}", b),
+            // unlike `Display`, `Debug` keeps the `.0` of whole floats.
+            Object::Float(x) => write!(f, "{:?}", x),
+            Object::Bool(b) => write!(f, "{}", b),
+            Object::Str(ref s) => write!(f, "{}", s),
+            Object::Array(ref elements) => {
+                write!(f, "[")?;
+                for (i, e) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    fmt_element(e, f)?;
+                }
+                write!(f, "]")
+            }
+            Object::Hash(ref pairs) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    fmt_element(&k.to_object(), f)?;
+                    write!(f, ": ")?;
+                    fmt_element(v, f)?;
+                }
+                write!(f, "}}")
+            }
+            Object::FnDef { ref args, .. } => write!(f, "fn({}) {{ ... }}", args.join(", ")),
+            Object::Builtin(b) => write!(f, "builtin {}", b.name()),
+            Object::Null => write!(f, "null"),
         }
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+pub enum RuntimeError {
+    UndefinedName {
+        name: String,
+        span: Span,
+    },
+    /// An operator applied to operands of types it does not support.
+    TypeError {
+        op: &'static str,
+        operands: Vec<&'static str>,
+        span: Span,
+    },
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    NotCallable {
+        type_name: &'static str,
+        span: Span,
+    },
+    IntegerOverflow {
+        span: Span,
+    },
+    /// A float that is infinite or NaN where an integer is needed.
+    FloatToInt {
+        value: f64,
+        span: Span,
+    },
+    /// `/` or `%` with a zero right operand.
+    DivisionByZero {
+        span: Span,
+    },
+    /// A hash key, in a literal or an index, of a type that is not hashable.
+    UnhashableKey {
+        type_name: &'static str,
+        span: Span,
+    },
+    /// `break` or `continue` that reached a function or program boundary.
+    OutsideLoop {
+        keyword: &'static str,
+        span: Span,
+    },
+    /// A call nested more than `MAX_CALL_DEPTH` deep, which is most likely
+    /// recursion that never ends.
+    RecursionLimit {
+        span: Span,
+    },
+}
+
+impl RuntimeError {
+    pub fn span(&self) -> Span {
+        match *self {
+            RuntimeError::UndefinedName { span, .. }
+            | RuntimeError::TypeError { span, .. }
+            | RuntimeError::ArityMismatch { span, .. }
+            | RuntimeError::NotCallable { span, .. }
+            | RuntimeError::IntegerOverflow { span }
+            | RuntimeError::DivisionByZero { span }
+            | RuntimeError::FloatToInt { span, .. }
+            | RuntimeError::UnhashableKey { span, .. }
+            | RuntimeError::OutsideLoop { span, .. }
+            | RuntimeError::RecursionLimit { span } => span,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuntimeError::UndefinedName { ref name, .. } => {
+                write!(f, "undefined variable `{}`", name)
+            }
+            RuntimeError::TypeError {
+                op, ref operands, ..
+            } => write!(
+                f,
+                "unsupported operand type for `{}`: {}",
+                op,
+                operands.join(" and ")
+            ),
+            RuntimeError::ArityMismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "function takes {} argument(s) but {} were given",
+                expected, found
+            ),
+            RuntimeError::NotCallable { type_name, .. } => {
+                write!(f, "{} is not callable", type_name)
+            }
+            RuntimeError::IntegerOverflow { .. } => write!(f, "integer overflow"),
+            RuntimeError::DivisionByZero { .. } => write!(f, "division by zero"),
+            RuntimeError::FloatToInt { value, .. } => {
+                write!(f, "cannot convert {:?} to an integer", value)
+            }
+            RuntimeError::UnhashableKey { type_name, .. } => {
+                write!(f, "{} cannot be used as a hash key", type_name)
+            }
+            RuntimeError::OutsideLoop { keyword, .. } => {
+                write!(f, "`{}` outside of a loop", keyword)
+            }
+            RuntimeError::RecursionLimit { .. } => {
+                write!(f, "calls nested more than {} deep", MAX_CALL_DEPTH)
+            }
+        }
+    }
+}
+
+impl error::Error for RuntimeError {}
+
+/// A scope of variables. Names not bound here are looked up in the `outer`
+/// scope: a function call gets a fresh scope whose outer one is the
+/// environment the function was defined in.
+///
+/// Blocks do not introduce scopes, so a `let` anywhere in a function body
+/// binds in the function's scope, shadowing any outer variable of that name.
+/// An assignment without `let` changes the outer variable instead.
+#[derive(Default)]
 pub struct Environment {
     store: HashMap<String, Object>,
+    outer: Option<Rc<RefCell<Environment>>>,
+}
+
+impl fmt::Debug for Environment {
+    // only the names: values may be closures capturing this environment.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names = self.store.keys().collect::<Vec<_>>();
+        names.sort();
+        f.debug_struct("Environment")
+            .field("names", &names)
+            .field("outer", &self.outer.as_ref().map(|_| ".."))
+            .finish()
+    }
 }
 
 impl Environment {
     pub fn new() -> Environment {
         Environment {
             store: HashMap::new(),
+            outer: None,
         }
     }
 
-    fn get(&self, name: String) -> Object {
-        if let Some(obj) = self.store.get(&name) {
-            obj.clone()
-        } else {
-            panic!("Undefined variable: {:?}", name);
+    pub fn enclosed(outer: Rc<RefCell<Environment>>) -> Environment {
+        Environment {
+            store: HashMap::new(),
+            outer: Some(outer),
         }
     }
 
+    fn get(&self, name: &str) -> Option<Object> {
+        match self.store.get(name) {
+            Some(obj) => Some(obj.clone()),
+            None => self.outer.as_ref().and_then(|o| o.borrow().get(name)),
+        }
+    }
+
+    // binds `name` in this scope.
     fn set(&mut self, name: String, value: Object) -> Object {
         let v = value.clone();
         self.store.insert(name, value);
         v
     }
+
+    // rebinds `name` in the nearest scope that binds it, returning `None`
+    // if none does.
+    fn set_existing(&mut self, name: &str, value: Object) -> Option<Object> {
+        match self.store.get_mut(name) {
+            Some(slot) => {
+                *slot = value.clone();
+                Some(value)
+            }
+            None => self
+                .outer
+                .as_ref()
+                .and_then(|o| o.borrow_mut().set_existing(name, value)),
+        }
+    }
+}
+
+// why evaluation of a node stopped before producing a value. `Return`
+// carries the returned value up to the enclosing function call, `Break` and
+// `Continue` go up to the enclosing loop.
+#[derive(Debug)]
+enum Unwind {
+    Return(Object),
+    Break(Span),
+    Continue(Span),
+    Error(RuntimeError),
+}
+
+// the value of a function body or a program: a `return` stops there, while
+// `break` and `continue` must not get that far.
+fn finish(result: Result<Object, Unwind>) -> Result<Object, RuntimeError> {
+    match result {
+        Ok(obj) | Err(Unwind::Return(obj)) => Ok(obj),
+        Err(Unwind::Break(span)) => Err(RuntimeError::OutsideLoop {
+            keyword: "break",
+            span,
+        }),
+        Err(Unwind::Continue(span)) => Err(RuntimeError::OutsideLoop {
+            keyword: "continue",
+            span,
+        }),
+        Err(Unwind::Error(e)) => Err(e),
+    }
 }
 
+// `0`, `0.0`, `false` and `null` are falsy, everything else is truthy.
+fn is_truthy(obj: &Object) -> bool {
+    match *obj {
+        Object::Integer(0) | Object::Bool(false) | Object::Null => false,
+        Object::Float(x) => x != 0.0,
+        _ => true,
+    }
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(e: RuntimeError) -> Unwind {
+        Unwind::Error(e)
+    }
+}
+
+/// How deeply calls to functions defined in Monkey may nest. Each call
+/// takes a few frames of the Rust stack, so runaway recursion stops with an
+/// error here rather than overflowing it.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+#[derive(Default)]
 pub struct Evaluator {
-    pub global_env: RefCell<Environment>,
+    pub global_env: Rc<RefCell<Environment>>,
+    // number of calls being evaluated.
+    depth: Cell<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Arith {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Arith {
+    fn symbol(self) -> &'static str {
+        match self {
+            Arith::Add => "+",
+            Arith::Sub => "-",
+            Arith::Mul => "*",
+            Arith::Div => "/",
+            Arith::Rem => "%",
+        }
+    }
+}
+
+// `l op r`. Besides numbers, `+` concatenates strings.
+fn arithmetic(op: Arith, l: Object, r: Object, span: Span) -> Result<Object, RuntimeError> {
+    match (l, r) {
+        (Object::Integer(l), Object::Integer(r)) => int_arithmetic(op, l, r, span),
+        (Object::Str(l), Object::Str(r)) if op == Arith::Add => Ok(Object::Str(l + &r)),
+        (l, r) => {
+            if let Some((l, r)) = float_operands(&l, &r) {
+                return Ok(Object::Float(float_arithmetic(op, l, r)));
+            }
+            #[cfg(feature = "bigint")]
+            if let (Some(l), Some(r)) = (to_big(&l), to_big(&r)) {
+                return big_arithmetic(op, l, r, span);
+            }
+            Err(type_error(op.symbol(), &l, &r, span))
+        }
+    }
+}
+
+// `l op r` on integers. Division and remainder round toward zero, so the
+// remainder has the sign of the dividend. Results that do not fit in an
+// `i64` are an overflow error, or with the `bigint` feature are promoted.
+fn int_arithmetic(op: Arith, l: i64, r: i64, span: Span) -> Result<Object, RuntimeError> {
+    let result = match op {
+        Arith::Add => l.checked_add(r),
+        Arith::Sub => l.checked_sub(r),
+        Arith::Mul => l.checked_mul(r),
+        Arith::Div | Arith::Rem if r == 0 => return Err(RuntimeError::DivisionByZero { span }),
+        Arith::Div => l.checked_div(r),
+        // `MIN / -1` overflows, but `MIN % -1` is just 0.
+        Arith::Rem => Some(l.wrapping_rem(r)),
+    };
+    match result {
+        Some(i) => Ok(Object::Integer(i)),
+        #[cfg(feature = "bigint")]
+        None => big_arithmetic(op, BigInt::from(l), BigInt::from(r), span),
+        #[cfg(not(feature = "bigint"))]
+        None => Err(RuntimeError::IntegerOverflow { span }),
+    }
+}
+
+#[cfg(feature = "bigint")]
+fn big_arithmetic(op: Arith, l: BigInt, r: BigInt, span: Span) -> Result<Object, RuntimeError> {
+    let result = match op {
+        Arith::Add => l + r,
+        Arith::Sub => l - r,
+        Arith::Mul => l * r,
+        Arith::Div | Arith::Rem if r.sign() == Sign::NoSign => {
+            return Err(RuntimeError::DivisionByZero { span })
+        }
+        Arith::Div => l / r,
+        Arith::Rem => l % r,
+    };
+    Ok(Object::from(result))
+}
+
+// the operands of a float operation: if one of `l` and `r` is a float and
+// the other one a number, both converted to floats.
+fn float_operands(l: &Object, r: &Object) -> Option<(f64, f64)> {
+    match (l, r) {
+        (Object::Float(_), _) | (_, Object::Float(_)) => Some((l.as_float()?, r.as_float()?)),
+        _ => None,
+    }
+}
+
+// `l op r` on floats, which follows IEEE 754: dividing by zero gives an
+// infinity or NaN rather than an error.
+fn float_arithmetic(op: Arith, l: f64, r: f64) -> f64 {
+    match op {
+        Arith::Add => l + r,
+        Arith::Sub => l - r,
+        Arith::Mul => l * r,
+        Arith::Div => l / r,
+        Arith::Rem => l % r,
+    }
+}
+
+// `obj` as a big integer, if it is an integer of either size.
+#[cfg(feature = "bigint")]
+fn to_big(obj: &Object) -> Option<BigInt> {
+    match *obj {
+        Object::Integer(i) => Some(BigInt::from(i)),
+        Object::BigInt(ref b) => Some(b.clone()),
+        _ => None,
+    }
+}
+
+// how `l` and `r` are ordered, if they are both numbers or both strings.
+fn ordering(l: &Object, r: &Object) -> Option<Ordering> {
+    match (l, r) {
+        (Object::Integer(l), Object::Integer(r)) => Some(l.cmp(r)),
+        (Object::Str(l), Object::Str(r)) => Some(l.cmp(r)),
+        #[cfg(feature = "bigint")]
+        _ => Some(to_big(l)?.cmp(&to_big(r)?)),
+        #[cfg(not(feature = "bigint"))]
+        _ => None,
+    }
+}
+
+// `l op r` for a comparison operator; `test` tells whether an ordering of
+// the operands satisfies it.
+fn compare(
+    op: &'static str,
+    l: Object,
+    r: Object,
+    span: Span,
+    test: fn(Ordering) -> bool,
+) -> Result<Object, RuntimeError> {
+    if let Some((l, r)) = float_operands(&l, &r) {
+        // NaN is unordered, so every comparison with it is false.
+        return Ok(Object::Bool(l.partial_cmp(&r).is_some_and(test)));
+    }
+    match ordering(&l, &r) {
+        Some(o) => Ok(Object::Bool(test(o))),
+        None => Err(type_error(op, &l, &r, span)),
+    }
+}
+
+// the element at `index`; negative indices count from the end, so `-1` is
+// the last element. Out of bounds indices yield null.
+fn index_array(elements: &[Object], index: i64) -> Object {
+    let len = elements.len() as i64;
+    let index = if index < 0 { len + index } else { index };
+    if 0 <= index && index < len {
+        elements[index as usize].clone()
+    } else {
+        Object::Null
+    }
+}
+
+fn hash_key(obj: &Object, span: Span) -> Result<HashKey, RuntimeError> {
+    HashKey::from_object(obj).ok_or(RuntimeError::UnhashableKey {
+        type_name: obj.type_name(),
+        span,
+    })
+}
+
+fn type_error(op: &'static str, l: &Object, r: &Object, span: Span) -> RuntimeError {
+    RuntimeError::TypeError {
+        op,
+        operands: vec![l.type_name(), r.type_name()],
+        span,
+    }
 }
 
 impl Evaluator {
     pub fn new() -> Self {
         let global_env = Environment::new();
         Evaluator {
-            global_env: RefCell::new(global_env),
+            global_env: Rc::new(RefCell::new(global_env)),
+            depth: Cell::new(0),
         }
     }
 
-    pub fn eval(&self, node: AST, env: &RefCell<Environment>) -> Object {
+    /// Evaluates a single node. A `return` outside of any function stops
+    /// evaluation of the node and yields the returned value.
+    pub fn eval(&self, node: AST, env: &Rc<RefCell<Environment>>) -> Result<Object, RuntimeError> {
+        finish(self.eval_node(node, env))
+    }
+
+    /// Evaluates a program statement by statement. A top-level `return` ends
+    /// the program early, and its value is the result.
+    pub fn eval_program(
+        &self,
+        stmts: Vec<AST>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Object, RuntimeError> {
+        finish(self.eval_stmts(stmts, env))
+    }
+
+    fn eval_node(&self, node: AST, env: &Rc<RefCell<Environment>>) -> Result<Object, Unwind> {
+        let span = node.span;
         match node.kind {
-            ASTKind::Int(i) => Object::Integer(i),
-            ASTKind::Add(lhs, rhs) => match (self.eval(*lhs, env), self.eval(*rhs, env)) {
-                (Object::Integer(l), Object::Integer(r)) => Object::Integer(l + r),
-                (_, _) => panic!("+ operator supports only integer"),
-            },
-            ASTKind::Minus(lhs, rhs) => match (self.eval(*lhs, env), self.eval(*rhs, env)) {
-                (Object::Integer(l), Object::Integer(r)) => Object::Integer(l + r),
-                (_, _) => panic!("+ operator supports only integer"),
-            },
-            ASTKind::Multi(lhs, rhs) => match (self.eval(*lhs, env), self.eval(*rhs, env)) {
-                (Object::Integer(l), Object::Integer(r)) => Object::Integer(l * r),
-                (_, _) => panic!("* operator supports only integer"),
-            },
-            ASTKind::LT(lhs, rhs) => match (self.eval(*lhs, env), self.eval(*rhs, env)) {
-                (Object::Integer(l), Object::Integer(r)) => Object::Bool(l < r),
-                (_, _) => panic!("< operator supports only integer"),
-            },
-            ASTKind::LTE(lhs, rhs) => match (self.eval(*lhs, env), self.eval(*rhs, env)) {
-                (Object::Integer(l), Object::Integer(r)) => Object::Bool(l <= r),
-                (_, _) => panic!("<= operator supports only integer"),
+            ASTKind::Int(i) => Ok(Object::Integer(i)),
+            #[cfg(feature = "bigint")]
+            ASTKind::BigInt(b) => Ok(Object::from(b)),
+            ASTKind::Float(x) => Ok(Object::Float(x)),
+            ASTKind::Str(s) => Ok(Object::Str(s)),
+            ASTKind::Neg(expr) => match self.eval_node(*expr, env)? {
+                Object::Integer(i) => Ok(int_arithmetic(Arith::Sub, 0, i, span)?),
+                #[cfg(feature = "bigint")]
+                Object::BigInt(b) => Ok(Object::from(-b)),
+                Object::Float(x) => Ok(Object::Float(-x)),
+                obj => Err(RuntimeError::TypeError {
+                    op: "-",
+                    operands: vec![obj.type_name()],
+                    span,
+                }
+                .into()),
             },
+            ASTKind::Not(expr) => Ok(Object::Bool(!is_truthy(&self.eval_node(*expr, env)?))),
+            ASTKind::Add(lhs, rhs) => {
+                let (l, r) = self.operands(*lhs, *rhs, env)?;
+                Ok(arithmetic(Arith::Add, l, r, span)?)
+            }
+            ASTKind::Minus(lhs, rhs) => {
+                let (l, r) = self.operands(*lhs, *rhs, env)?;
+                Ok(arithmetic(Arith::Sub, l, r, span)?)
+            }
+            ASTKind::Multi(lhs, rhs) => {
+                let (l, r) = self.operands(*lhs, *rhs, env)?;
+                Ok(arithmetic(Arith::Mul, l, r, span)?)
+            }
+            ASTKind::Div(lhs, rhs) => {
+                let (l, r) = self.operands(*lhs, *rhs, env)?;
+                Ok(arithmetic(Arith::Div, l, r, span)?)
+            }
+            ASTKind::Mod(lhs, rhs) => {
+                let (l, r) = self.operands(*lhs, *rhs, env)?;
+                Ok(arithmetic(Arith::Rem, l, r, span)?)
+            }
+            ASTKind::LT(lhs, rhs) => {
+                let (l, r) = self.operands(*lhs, *rhs, env)?;
+                Ok(compare("<", l, r, span, Ordering::is_lt)?)
+            }
+            ASTKind::LTE(lhs, rhs) => {
+                let (l, r) = self.operands(*lhs, *rhs, env)?;
+                Ok(compare("<=", l, r, span, Ordering::is_le)?)
+            }
+            ASTKind::GT(lhs, rhs) => {
+                let (l, r) = self.operands(*lhs, *rhs, env)?;
+                Ok(compare(">", l, r, span, Ordering::is_gt)?)
+            }
+            ASTKind::GTE(lhs, rhs) => {
+                let (l, r) = self.operands(*lhs, *rhs, env)?;
+                Ok(compare(">=", l, r, span, Ordering::is_ge)?)
+            }
+            // values of different types are never equal, except integers
+            // and floats of the same value.
+            ASTKind::Eq(lhs, rhs) => Ok(Object::Bool(
+                self.eval_node(*lhs, env)? == self.eval_node(*rhs, env)?,
+            )),
+            ASTKind::NotEq(lhs, rhs) => Ok(Object::Bool(
+                self.eval_node(*lhs, env)? != self.eval_node(*rhs, env)?,
+            )),
+            ASTKind::And(lhs, rhs) => Ok(Object::Bool(
+                is_truthy(&self.eval_node(*lhs, env)?) && is_truthy(&self.eval_node(*rhs, env)?),
+            )),
+            ASTKind::Or(lhs, rhs) => Ok(Object::Bool(
+                is_truthy(&self.eval_node(*lhs, env)?) || is_truthy(&self.eval_node(*rhs, env)?),
+            )),
             ASTKind::If {
                 cond,
                 stmt,
                 else_stmt,
-            } => match self.eval(*cond, env) {
-                Object::Integer(0) | Object::Bool(false) | Object::Null => {
-                    if let Some(else_stmt) = else_stmt {
-                        self.eval(*else_stmt, env)
-                    } else {
-                        Object::Null
-                    }
+            } => {
+                if is_truthy(&self.eval_node(*cond, env)?) {
+                    self.eval_node(*stmt, env)
+                } else if let Some(else_stmt) = else_stmt {
+                    self.eval_node(*else_stmt, env)
+                } else {
+                    Ok(Object::Null)
                 }
-                _ => self.eval(*stmt, env),
-            },
-            ASTKind::Bool(b) => Object::Bool(b),
-            ASTKind::Return(expr) => self.eval(*expr, env),
-            ASTKind::Compound(stmts) => {
-                let mut v = vec![];
-                for s in stmts {
-                    v.push(self.eval(s, env))
+            }
+            ASTKind::While { cond, stmt } => {
+                while is_truthy(&self.eval_node((*cond).clone(), env)?) {
+                    match self.eval_node((*stmt).clone(), env) {
+                        Ok(_) | Err(Unwind::Continue(_)) => {}
+                        Err(Unwind::Break(_)) => break,
+                        Err(e) => return Err(e),
+                    }
                 }
-                v.get(v.len() - 1).unwrap().clone() // todo: fix proper way to return last evaluated obj
+                Ok(Object::Null)
             }
+            ASTKind::Break => Err(Unwind::Break(span)),
+            ASTKind::Continue => Err(Unwind::Continue(span)),
+            ASTKind::Bool(b) => Ok(Object::Bool(b)),
+            ASTKind::Return(expr) => Err(Unwind::Return(self.eval_node(*expr, env)?)),
+            ASTKind::Compound(stmts) => self.eval_stmts(stmts, env),
             ASTKind::Let { name, expr } => {
-                let value = self.eval(*expr, env);
+                let value = self.eval_node(*expr, env)?;
                 let mut env = env.borrow_mut();
-                env.set(name, value)
+                Ok(env.set(name, value))
             }
-            ASTKind::Ident(s) => env.borrow().get(s),
-            ASTKind::FnDef { args, stmts } => Object::func(args, stmts),
-            ASTKind::FnCall { name, args: exprs } => {
-                let values: Vec<Object> = exprs.into_iter().map(|x| self.eval(x, &env)).collect();
-                let fnobj = self.eval(AST::ident(name.clone()), env);
-                if let Object::FnDef { args, stmts, env } = fnobj.clone() {
-                    env.borrow_mut().set(name, fnobj);
-                    for (name, value) in args.iter().zip(values.iter()) {
-                        env.borrow_mut().set(name.clone(), value.clone());
-                    }
-                    let mut v = vec![];
-                    for s in stmts {
-                        v.push(self.eval(s, &env))
-                    }
-                    v.get(v.len() - 1).unwrap().clone() // todo: fix proper way to return last evaluated obj
-                } else {
-                    panic!("you tried to call undefined function.");
+            ASTKind::Assign { name, expr } => {
+                let value = self.eval_node(*expr, env)?;
+                match env.borrow_mut().set_existing(&name, value) {
+                    Some(value) => Ok(value),
+                    None => Err(RuntimeError::UndefinedName { name, span }.into()),
+                }
+            }
+            ASTKind::Ident(s) => match env.borrow().get(&s) {
+                Some(obj) => Ok(obj),
+                None => match Builtin::lookup(&s) {
+                    Some(b) => Ok(Object::Builtin(b)),
+                    None => Err(RuntimeError::UndefinedName { name: s, span }.into()),
+                },
+            },
+            ASTKind::Array(elements) => {
+                let values = elements
+                    .into_iter()
+                    .map(|x| self.eval_node(x, env))
+                    .collect::<Result<Vec<Object>, Unwind>>()?;
+                Ok(Object::Array(Rc::new(values)))
+            }
+            ASTKind::Hash(pairs) => {
+                let mut hash = BTreeMap::new();
+                for (key, value) in pairs {
+                    let key_span = key.span;
+                    let key = hash_key(&self.eval_node(key, env)?, key_span)?;
+                    hash.insert(key, self.eval_node(value, env)?);
+                }
+                Ok(Object::Hash(Rc::new(hash)))
+            }
+            ASTKind::Index { left, index } => {
+                let index_span = index.span;
+                match (self.eval_node(*left, env)?, self.eval_node(*index, env)?) {
+                    (Object::Array(a), Object::Integer(i)) => Ok(index_array(&a, i)),
+                    #[cfg(feature = "bigint")]
+                    (Object::Array(_), Object::BigInt(_)) => Ok(Object::Null),
+                    // missing keys yield null.
+                    (Object::Hash(h), key) => Ok(h
+                        .get(&hash_key(&key, index_span)?)
+                        .cloned()
+                        .unwrap_or(Object::Null)),
+                    (l, r) => Err(type_error("[]", &l, &r, span).into()),
                 }
             }
-            _ => unimplemented!(),
+            ASTKind::FnDef { args, stmts } => Ok(Object::func(args, stmts, env.clone())),
+            ASTKind::FnCall { callee, args } => {
+                let callee_span = callee.span;
+                let func = self.eval_node(*callee, env)?;
+                let values = args
+                    .into_iter()
+                    .map(|x| self.eval_node(x, env))
+                    .collect::<Result<Vec<Object>, Unwind>>()?;
+                Ok(self.call(func, values, callee_span, span)?)
+            }
+        }
+    }
+
+    // calls `func` with `values`; `callee_span` is where the function came
+    // from, `span` the whole call.
+    fn call(
+        &self,
+        func: Object,
+        values: Vec<Object>,
+        callee_span: Span,
+        span: Span,
+    ) -> Result<Object, RuntimeError> {
+        match func {
+            Object::FnDef { args, stmts, env } => {
+                if args.len() != values.len() {
+                    return Err(RuntimeError::ArityMismatch {
+                        expected: args.len(),
+                        found: values.len(),
+                        span,
+                    });
+                }
+                let mut frame = Environment::enclosed(env);
+                for (name, value) in args.into_iter().zip(values) {
+                    frame.set(name, value);
+                }
+                let env = Rc::new(RefCell::new(frame));
+                let depth = self.depth.get();
+                if depth == MAX_CALL_DEPTH {
+                    return Err(RuntimeError::RecursionLimit { span });
+                }
+                self.depth.set(depth + 1);
+                let result = finish(self.eval_stmts(stmts, &env));
+                self.depth.set(depth);
+                result
+            }
+            Object::Builtin(b) => b.call(values, span),
+            obj => Err(RuntimeError::NotCallable {
+                type_name: obj.type_name(),
+                span: callee_span,
+            }),
         }
     }
+
+    // evaluates the operands of a binary operator, left to right.
+    fn operands(
+        &self,
+        lhs: AST,
+        rhs: AST,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<(Object, Object), Unwind> {
+        let l = self.eval_node(lhs, env)?;
+        Ok((l, self.eval_node(rhs, env)?))
+    }
+
+    // evaluates `stmts` in order; the value is that of the last one, or null.
+    fn eval_stmts(
+        &self,
+        stmts: Vec<AST>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Object, Unwind> {
+        let mut result = Object::Null;
+        for s in stmts {
+            result = self.eval_node(s, env)?;
+        }
+        Ok(result)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Evaluator, Object, AST};
+    use super::{Evaluator, Object, RuntimeError, AST, MAX_CALL_DEPTH};
+    use ast::Parser;
+    use lexer::Lexer;
+    use span::Span;
+    use std::thread;
     #[test]
     fn eval_add() {
         let ev = Evaluator::new();
         assert_eq!(
-            Object::Integer(6),
+            Ok(Object::Integer(6)),
             ev.eval(
                 AST::add(AST::add(AST::int(1), AST::int(3)), AST::int(2)),
                 &ev.global_env
@@ -157,7 +878,7 @@ mod tests {
     fn eval_multi() {
         let ev = Evaluator::new();
         assert_eq!(
-            Object::Integer(100),
+            Ok(Object::Integer(100)),
             ev.eval(AST::multi(AST::int(20), AST::int(5)), &ev.global_env)
         )
     }
@@ -166,35 +887,35 @@ mod tests {
     fn eval_if() {
         let ev = Evaluator::new();
         assert_eq!(
-            Object::Integer(0),
+            Ok(Object::Integer(0)),
             ev.eval(
                 AST::if_stmt(AST::bool(true), AST::int(0), None),
                 &ev.global_env
             )
         );
         assert_eq!(
-            Object::Null,
+            Ok(Object::Null),
             ev.eval(
                 AST::if_stmt(AST::bool(false), AST::int(0), None),
                 &ev.global_env
             )
         );
         assert_eq!(
-            Object::Integer(2),
+            Ok(Object::Integer(2)),
             ev.eval(
                 AST::if_stmt(AST::bool(false), AST::int(0), Some(AST::int(2))),
                 &ev.global_env
             )
         );
         assert_eq!(
-            Object::Integer(0),
+            Ok(Object::Integer(0)),
             ev.eval(
                 AST::if_stmt(AST::int(1), AST::int(0), Some(AST::int(2))),
                 &ev.global_env
             )
         );
         assert_eq!(
-            Object::Integer(2),
+            Ok(Object::Integer(2)),
             ev.eval(
                 AST::if_stmt(
                     AST::add(AST::int(1), AST::int(-1)),
@@ -205,7 +926,7 @@ mod tests {
             )
         );
         assert_eq!(
-            Object::Integer(20),
+            Ok(Object::Integer(20)),
             ev.eval(
                 AST::if_stmt(
                     AST::bool(true),
@@ -221,7 +942,7 @@ mod tests {
     fn eval_return() {
         let ev = Evaluator::new();
         assert_eq!(
-            Object::Integer(2),
+            Ok(Object::Integer(2)),
             ev.eval(
                 AST::return_stmt(AST::add(AST::int(1), AST::int(1))),
                 &ev.global_env
@@ -233,7 +954,7 @@ mod tests {
     fn eval_compound() {
         let ev = Evaluator::new();
         assert_eq!(
-            Object::Integer(10),
+            Ok(Object::Integer(10)),
             ev.eval(
                 AST::compound_statement(vec![AST::int(2), AST::int(10)]),
                 &ev.global_env
@@ -245,7 +966,7 @@ mod tests {
     fn eval_relational() {
         let ev = Evaluator::new();
         assert_eq!(
-            Object::Bool(true),
+            Ok(Object::Bool(true)),
             ev.eval(AST::lt(AST::int(1), AST::int(2)), &ev.global_env)
         );
     }
@@ -254,16 +975,16 @@ mod tests {
     fn eval_let() {
         let ev = Evaluator::new();
         assert_eq!(
-            Object::Integer(2),
+            Ok(Object::Integer(2)),
             ev.eval(AST::let_stmt("x".to_string(), AST::int(2)), &ev.global_env)
         );
 
         assert_eq!(
-            Object::Integer(2),
+            Ok(Object::Integer(2)),
             ev.eval(AST::ident("x".to_string()), &ev.global_env)
         );
         assert_eq!(
-            Object::Integer(3),
+            Ok(Object::Integer(3)),
             ev.eval(
                 AST::add(AST::ident("x".to_string()), AST::int(1)),
                 &ev.global_env
@@ -271,6 +992,14 @@ mod tests {
         )
     }
 
+    #[test]
+    fn eval_unicode_identifiers() {
+        assert_eq!(
+            run("let café = 2; let x2 = café * 3; let 変数 = x2 + 1; 変数;"),
+            Ok(Object::Integer(7))
+        );
+    }
+
     #[test]
     fn eval_func() {
         let ev = Evaluator::new();
@@ -280,10 +1009,14 @@ mod tests {
                 AST::fn_def(vec![], vec![AST::return_stmt(AST::int(1))]),
             ),
             &ev.global_env,
-        );
+        )
+        .unwrap();
         assert_eq!(
-            Object::Integer(1),
-            ev.eval(AST::fn_call("x".to_string(), vec![]), &ev.global_env)
+            Ok(Object::Integer(1)),
+            ev.eval(
+                AST::fn_call(AST::ident("x".to_string()), vec![]),
+                &ev.global_env
+            )
         );
 
         // let x = fn(x) {  return x + 1;}
@@ -299,12 +1032,13 @@ mod tests {
                 ),
             ),
             &ev.global_env,
-        );
+        )
+        .unwrap();
 
         assert_eq!(
-            Object::Integer(2),
+            Ok(Object::Integer(2)),
             ev.eval(
-                AST::fn_call("x".to_string(), vec![AST::int(1)]),
+                AST::fn_call(AST::ident("x".to_string()), vec![AST::int(1)]),
                 &ev.global_env
             )
         );
@@ -323,31 +1057,744 @@ mod tests {
                 AST::fn_def(
                     vec!["f".to_string(), "x".to_string()],
                     vec![AST::fn_call(
-                        "f".to_string(),
+                        AST::ident("f".to_string()),
                         vec![AST::fn_call(
-                            "f".to_string(),
+                            AST::ident("f".to_string()),
                             vec![AST::ident("x".to_string())],
                         )],
                     )],
                 ),
             ),
             &ev.global_env,
-        );
+        )
+        .unwrap();
         assert_eq!(
-            Object::Integer(2),
+            Ok(Object::Integer(2)),
             ev.eval(
                 AST::fn_call(
-                    "twice".to_string(),
-                    vec![AST::fn_def(
-                        vec!["a".to_string()],
-                        vec![AST::return_stmt(AST::add(
-                            AST::ident("a".to_string()),
-                            AST::int(1)
-                        ))]
-                    ), AST::int(0)]
+                    AST::ident("twice".to_string()),
+                    vec![
+                        AST::fn_def(
+                            vec!["a".to_string()],
+                            vec![AST::return_stmt(AST::add(
+                                AST::ident("a".to_string()),
+                                AST::int(1)
+                            ))]
+                        ),
+                        AST::int(0)
+                    ]
                 ),
                 &ev.global_env
             )
         );
     }
+
+    #[test]
+    fn eval_minus() {
+        let ev = Evaluator::new();
+        assert_eq!(
+            Ok(Object::Integer(-3)),
+            ev.eval(AST::minus(AST::int(2), AST::int(5)), &ev.global_env)
+        );
+    }
+
+    #[test]
+    fn eval_errors() {
+        let ev = Evaluator::new();
+        assert_eq!(
+            Err(RuntimeError::UndefinedName {
+                name: "y".to_string(),
+                span: Span::default(),
+            }),
+            ev.eval(AST::ident("y".to_string()), &ev.global_env)
+        );
+        assert_eq!(
+            Err(RuntimeError::TypeError {
+                op: "+",
+                operands: vec!["integer", "bool"],
+                span: Span::default(),
+            }),
+            ev.eval(AST::add(AST::int(1), AST::bool(true)), &ev.global_env)
+        );
+        #[cfg(not(feature = "bigint"))]
+        assert_eq!(
+            Err(RuntimeError::IntegerOverflow {
+                span: Span::default(),
+            }),
+            ev.eval(AST::multi(AST::int(i64::MAX), AST::int(2)), &ev.global_env)
+        );
+
+        ev.eval(AST::let_stmt("x".to_string(), AST::int(1)), &ev.global_env)
+            .unwrap();
+        assert_eq!(
+            Err(RuntimeError::NotCallable {
+                type_name: "integer",
+                span: Span::default(),
+            }),
+            ev.eval(
+                AST::fn_call(AST::ident("x".to_string()), vec![]),
+                &ev.global_env
+            )
+        );
+
+        ev.eval(
+            AST::let_stmt(
+                "f".to_string(),
+                AST::fn_def(vec!["a".to_string()], vec![AST::ident("a".to_string())]),
+            ),
+            &ev.global_env,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(RuntimeError::ArityMismatch {
+                expected: 1,
+                found: 2,
+                span: Span::default(),
+            }),
+            ev.eval(
+                AST::fn_call(AST::ident("f".to_string()), vec![AST::int(1), AST::int(2)]),
+                &ev.global_env
+            )
+        );
+    }
+
+    fn run(src: &str) -> Result<Object, RuntimeError> {
+        let ev = Evaluator::new();
+        ev.eval_program(
+            Parser::new(Lexer::new(src)).parse().unwrap(),
+            &ev.global_env,
+        )
+    }
+
+    #[test]
+    fn runtime_error_span() {
+        let src = "let x = 1;\nx + true;";
+        let span = run(src).unwrap_err().span();
+        assert_eq!(&src[span.start.offset..span.end.offset], "x + true");
+    }
+
+    #[test]
+    fn eval_early_return() {
+        let src = "
+            let f = fn(x) {
+                if x < 10 {
+                    return 1;
+                }
+                2;
+            };
+            f(5) + f(20);";
+        assert_eq!(Ok(Object::Integer(3)), run(src));
+
+        // a return in an inner function does not leave the outer one.
+        let src = "
+            let g = fn(x) {
+                let inner = fn(y) { return y; 100; };
+                inner(x) + 1;
+            };
+            g(1);";
+        assert_eq!(Ok(Object::Integer(2)), run(src));
+    }
+
+    #[test]
+    fn eval_top_level_return() {
+        assert_eq!(Ok(Object::Integer(1)), run("return 1; 2;"));
+        assert_eq!(Ok(Object::Integer(1)), run("if true { return 1; } 2;"));
+    }
+
+    #[test]
+    fn eval_lexical_scope() {
+        // globals are visible in functions, and are looked up at call time.
+        let src = "
+            let f = fn(x) { x + y; };
+            let y = 10;
+            f(1);";
+        assert_eq!(Ok(Object::Integer(11)), run(src));
+
+        // parameters and `let`s shadow outer variables without touching them.
+        let src = "
+            let x = 1;
+            let f = fn(x) { let y = x * 2; y; };
+            let y = 5;
+            f(100) + x + y;";
+        assert_eq!(Ok(Object::Integer(206)), run(src));
+
+        // bindings of one call do not leak into the next.
+        let src = "
+            let f = fn(first) {
+                if first { let leaked = 1; }
+                leaked;
+            };
+            f(true);
+            f(false);";
+        assert_eq!(
+            "undefined variable `leaked`",
+            run(src).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn eval_closures() {
+        let src = "
+            let make_adder = fn(n) { fn(x) { x + n; }; };
+            let add_one = make_adder(1);
+            let add_ten = make_adder(10);
+            add_one(2) * add_ten(2);";
+        assert_eq!(Ok(Object::Integer(36)), run(src));
+
+        // recursion through the captured environment.
+        let src = "
+            let count = fn(n, acc) {
+                if n < 1 { return acc; }
+                count(n - 1, acc + 2);
+            };
+            count(5, 0);";
+        assert_eq!(Ok(Object::Integer(10)), run(src));
+
+        // a closure keeps the frame of the call that created it.
+        let src = "
+            let outer = fn(a) {
+                let b = a * 3;
+                fn(c) { a + b + c; };
+            };
+            let g = outer(2);
+            let a = 1000;
+            g(1);";
+        assert_eq!(Ok(Object::Integer(9)), run(src));
+    }
+
+    #[test]
+    fn eval_recursion_limit() {
+        // calls as deep as the limit take more stack than a test thread has.
+        let test = || {
+            let src = format!(
+                "let f = fn(n) {{ if n < 2 {{ return 1; }} f(n - 1) + 1; }}; f({});",
+                MAX_CALL_DEPTH
+            );
+            assert_eq!(run(&src), Ok(Object::Integer(MAX_CALL_DEPTH as i64)));
+
+            let src = "let f = fn(n) { f(n + 1); }; f(0);";
+            assert!(matches!(
+                run(src),
+                Err(RuntimeError::RecursionLimit { span }) if span.start.offset == 16
+            ));
+
+            // the depth is back to zero after the error.
+            let ev = Evaluator::new();
+            let parse = |src: &str| Parser::new(Lexer::new(src)).parse().unwrap();
+            assert!(ev.eval_program(parse(src), &ev.global_env).is_err());
+            let src = "let g = fn(n) { if n < 2 { return 1; } g(n - 1) + 1; }; g(10);";
+            assert_eq!(
+                ev.eval_program(parse(src), &ev.global_env),
+                Ok(Object::Integer(10))
+            );
+        };
+        thread::Builder::new()
+            .stack_size(256 << 20)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn eval_assignment() {
+        // a counter: assignment updates the captured variable, so each call
+        // sees the previous one's change, and counters do not share state.
+        let src = "
+            let counter = fn(n) { fn(step) { n = n + step; n; }; };
+            let inc = counter(0);
+            let other = counter(0);
+            inc(1);
+            inc(1);
+            other(1);
+            inc(1);";
+        assert_eq!(Ok(Object::Integer(3)), run(src));
+
+        // assignment inside a function changes the global, `let` shadows it.
+        let src = "
+            let x = 1;
+            let set = fn(v) { x = v; };
+            let shadow = fn(v) { let x = 10; x = v; };
+            set(2);
+            shadow(20);
+            x;";
+        assert_eq!(Ok(Object::Integer(2)), run(src));
+        assert_eq!(
+            Ok(Object::Integer(5)),
+            run("let i = 0; while i < 5 { i = i + 1; } i;")
+        );
+        assert!(matches!(
+            run("y = 1;"),
+            Err(RuntimeError::UndefinedName { ref name, .. }) if name == "y"
+        ));
+    }
+
+    #[test]
+    fn eval_while() {
+        let src = "
+            let i = 0;
+            let sum = 0;
+            while i < 10 {
+                let i = i + 1;
+                if i < 3 { continue; }
+                if 7 < i { break; }
+                let sum = sum + i;
+            }
+            sum;";
+        assert_eq!(Ok(Object::Integer(25)), run(src));
+
+        // `return` leaves the loop and the function.
+        let src = "
+            let find = fn(limit) {
+                let i = 0;
+                while true {
+                    let square = i * i;
+                    if limit < square { return i; }
+                    let i = i + 1;
+                }
+            };
+            find(50);";
+        assert_eq!(Ok(Object::Integer(8)), run(src));
+
+        let src = "while false { 1; }";
+        assert_eq!(Ok(Object::Null), run(src));
+    }
+
+    #[test]
+    fn eval_break_outside_loop() {
+        let ev = Evaluator::new();
+        assert_eq!(
+            Err(RuntimeError::OutsideLoop {
+                keyword: "break",
+                span: Span::default(),
+            }),
+            ev.eval(AST::break_stmt(), &ev.global_env)
+        );
+        ev.eval(
+            AST::let_stmt(
+                "f".to_string(),
+                AST::fn_def(vec![], vec![AST::continue_stmt()]),
+            ),
+            &ev.global_env,
+        )
+        .unwrap();
+        let call = AST::fn_call(AST::ident("f".to_string()), vec![]);
+        assert_eq!(
+            Err(RuntimeError::OutsideLoop {
+                keyword: "continue",
+                span: Span::default(),
+            }),
+            ev.eval(AST::while_stmt(AST::bool(true), call), &ev.global_env)
+        );
+    }
+
+    #[test]
+    fn eval_comparison() {
+        let cases = vec![
+            ("1 < 2;", true),
+            ("2 <= 2;", true),
+            ("3 > 2;", true),
+            ("2 >= 3;", false),
+            ("1 == 1;", true),
+            ("1 != 1;", false),
+            ("true == true;", true),
+            ("true != false;", true),
+            ("1 == true;", false),
+            ("1 < 2 == true;", true),
+            ("let f = fn(x) { x; }; f == f;", true),
+            ("let g = fn(x) { x; }; let h = fn(x) { x; }; g == h;", true),
+            ("let g = fn(x) { x; }; let h = fn(y) { y; }; g == h;", false),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(Ok(Object::Bool(expected)), run(src), "{}", src);
+        }
+        assert_eq!(
+            "unsupported operand type for `>`: bool and integer",
+            run("true > 1;").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn eval_prefix() {
+        let cases = vec![
+            ("-5;", Object::Integer(-5)),
+            ("--5;", Object::Integer(5)),
+            ("1 - -2 * 3;", Object::Integer(7)),
+            ("let x = 4; -x + 1;", Object::Integer(-3)),
+            ("!true;", Object::Bool(false)),
+            ("!0;", Object::Bool(true)),
+            ("!5;", Object::Bool(false)),
+            ("!!5;", Object::Bool(true)),
+            ("let f = fn(x) { x; }; !f(0);", Object::Bool(true)),
+            ("!-1 == false;", Object::Bool(true)),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(Ok(expected), run(src), "{}", src);
+        }
+        assert_eq!(
+            "unsupported operand type for `-`: bool",
+            run("-true;").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn eval_call_expressions() {
+        let cases = vec![
+            ("fn(x) { x * 2; }(21);", 42),
+            ("let answer = fn() { 42; }; answer();", 42),
+            ("fn(a, b,) { a - b; }(50, 8,);", 42),
+            (
+                "let make_adder = fn(n) { fn(x) { x + n; }; }; make_adder(1)(2);",
+                3,
+            ),
+            (
+                "let twice = fn(f) { fn(x) { f(f(x)); }; }; twice(fn(x) { x + 3; })(1);",
+                7,
+            ),
+            ("(fn(x) { fn(y) { x - y; }; })(10)(4);", 6),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(Ok(Object::Integer(expected)), run(src), "{}", src);
+        }
+
+        let src = "let x = 1;\nx(2);";
+        let e = run(src).unwrap_err();
+        assert_eq!("integer is not callable", e.to_string());
+        assert_eq!(&src[e.span().start.offset..e.span().end.offset], "x");
+
+        let src = "fn(a, b) { a; }(1)(2);";
+        let e = run(src).unwrap_err();
+        assert_eq!(
+            "function takes 2 argument(s) but 1 were given",
+            e.to_string()
+        );
+        assert_eq!(
+            &src[e.span().start.offset..e.span().end.offset],
+            "fn(a, b) { a; }(1)"
+        );
+    }
+
+    #[test]
+    fn eval_strings() {
+        assert_eq!(
+            run(r#"let s = "foo" + "bar"; s + "\n";"#),
+            Ok(Object::Str("foobar\n".to_string()))
+        );
+        assert_eq!(run(r#""a" == "a";"#), Ok(Object::Bool(true)));
+        assert_eq!(run(r#""a" != "b";"#), Ok(Object::Bool(true)));
+        assert_eq!(run(r#""a" == 1;"#), Ok(Object::Bool(false)));
+        assert_eq!(run(r#""abc" < "abd";"#), Ok(Object::Bool(true)));
+        assert_eq!(run(r#""b" >= "abc";"#), Ok(Object::Bool(true)));
+        assert_eq!(run(r#""" <= "";"#), Ok(Object::Bool(true)));
+        assert!(matches!(
+            run(r#""a" + 1;"#),
+            Err(RuntimeError::TypeError { op: "+", .. })
+        ));
+        assert!(matches!(
+            run(r#""a" < 1;"#),
+            Err(RuntimeError::TypeError { op: "<", .. })
+        ));
+    }
+
+    #[test]
+    fn display_object() {
+        assert_eq!(Object::Str("a \"b\"".to_string()).to_string(), "a \"b\"");
+        assert_eq!(Object::Integer(-3).to_string(), "-3");
+        assert_eq!(Object::Null.to_string(), "null");
+        assert_eq!(
+            run("fn(x, y) { x; };").unwrap().to_string(),
+            "fn(x, y) { ... }"
+        );
+    }
+
+    #[test]
+    fn eval_arrays() {
+        let src = "let a = [1, 2 * 3, \"x\"]; ";
+        let run_with = |expr: &str| run(&format!("{}{}", src, expr));
+        assert_eq!(run_with("a[1];"), Ok(Object::Integer(6)));
+        assert_eq!(run_with("a[-1];"), Ok(Object::Str("x".to_string())));
+        assert_eq!(run_with("a[-3];"), Ok(Object::Integer(1)));
+        assert_eq!(run_with("a[3];"), Ok(Object::Null));
+        assert_eq!(run_with("a[-4];"), Ok(Object::Null));
+        assert_eq!(run_with("[[1, 2]][0][1];"), Ok(Object::Integer(2)));
+        assert_eq!(run_with("a == [1, 6, \"x\"];"), Ok(Object::Bool(true)));
+        assert_eq!(run_with("a;").unwrap().to_string(), "[1, 6, \"x\"]");
+        assert!(matches!(
+            run_with("a[true];"),
+            Err(RuntimeError::TypeError { op: "[]", .. })
+        ));
+        assert!(matches!(
+            run_with("1[0];"),
+            Err(RuntimeError::TypeError { op: "[]", .. })
+        ));
+    }
+
+    #[test]
+    fn eval_array_builtins() {
+        let src = "let a = [1, 2, 3]; let e = []; ";
+        let run_with = |expr: &str| run(&format!("{}{}", src, expr));
+        assert_eq!(run_with("len(a);"), Ok(Object::Integer(3)));
+        assert_eq!(run_with("len(\"h\\u{e9}!\");"), Ok(Object::Integer(3)));
+        assert_eq!(run_with("first(a);"), Ok(Object::Integer(1)));
+        assert_eq!(run_with("last(a);"), Ok(Object::Integer(3)));
+        assert_eq!(run_with("first(e);"), Ok(Object::Null));
+        assert_eq!(run_with("last(e);"), Ok(Object::Null));
+        assert_eq!(run_with("rest(e);"), Ok(Object::Null));
+        assert_eq!(run_with("rest(a) == [2, 3];"), Ok(Object::Bool(true)));
+        assert_eq!(
+            run_with("let b = push(a, 4); [len(a), len(b), b[3]] == [3, 4, 4];"),
+            Ok(Object::Bool(true))
+        );
+        // a `let` shadows a builtin.
+        assert_eq!(run_with("let len = 7; len;"), Ok(Object::Integer(7)));
+        assert!(matches!(
+            run_with("len(a, a);"),
+            Err(RuntimeError::ArityMismatch {
+                expected: 1,
+                found: 2,
+                ..
+            })
+        ));
+        assert!(matches!(
+            run_with("first(1);"),
+            Err(RuntimeError::TypeError { op: "first", .. })
+        ));
+    }
+
+    #[test]
+    fn eval_hashes() {
+        let src = r#"let k = "na" + "me"; let h = {k: "monkey", 1: true, false: [1]}; "#;
+        let run_with = |expr: &str| run(&format!("{}{}", src, expr));
+        assert_eq!(
+            run_with(r#"h["name"];"#),
+            Ok(Object::Str("monkey".to_string()))
+        );
+        assert_eq!(run_with("h[2 - 1];"), Ok(Object::Bool(true)));
+        assert_eq!(run_with("h[1 == 2][0];"), Ok(Object::Integer(1)));
+        assert_eq!(run_with("h[2];"), Ok(Object::Null));
+        assert_eq!(run_with("len(h);"), Ok(Object::Integer(3)));
+        assert_eq!(run_with("{1: 1, 1: 2}[1];"), Ok(Object::Integer(2)));
+        assert_eq!(
+            run_with("h == {1: true, false: [1], \"name\": \"monkey\"};"),
+            Ok(Object::Bool(true))
+        );
+        assert_eq!(
+            run_with("h;").unwrap().to_string(),
+            r#"{1: true, false: [1], "name": "monkey"}"#
+        );
+        let e = run_with("{len: 1};").unwrap_err();
+        assert_eq!(e.to_string(), "function cannot be used as a hash key");
+        assert_eq!(e.span().start.column, src.len() + 2);
+        assert!(matches!(
+            run_with("h[[1]];"),
+            Err(RuntimeError::UnhashableKey {
+                type_name: "array",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn eval_division() {
+        assert_eq!(run("7 / 2;"), Ok(Object::Integer(3)));
+        assert_eq!(run("-7 / 2;"), Ok(Object::Integer(-3)));
+        assert_eq!(run("7 % 3;"), Ok(Object::Integer(1)));
+        assert_eq!(run("-7 % 3;"), Ok(Object::Integer(-1)));
+        assert_eq!(run("7 % -3;"), Ok(Object::Integer(1)));
+        assert_eq!(
+            run("let min = -9223372036854775808; min % -1;"),
+            Ok(Object::Integer(0))
+        );
+        #[cfg(not(feature = "bigint"))]
+        assert!(matches!(
+            run("let min = -9223372036854775808; min / -1;"),
+            Err(RuntimeError::IntegerOverflow { .. })
+        ));
+        assert!(matches!(
+            run("1 / 0;"),
+            Err(RuntimeError::DivisionByZero { .. })
+        ));
+        assert!(matches!(
+            run("1 % (2 - 2);"),
+            Err(RuntimeError::DivisionByZero { .. })
+        ));
+        assert!(matches!(
+            run("true / 1;"),
+            Err(RuntimeError::TypeError { op: "/", .. })
+        ));
+    }
+
+    #[test]
+    fn eval_64_bit_integers() {
+        assert_eq!(run("3000000000 * 3;"), Ok(Object::Integer(9_000_000_000)));
+        assert_eq!(run("-9223372036854775808;"), Ok(Object::Integer(i64::MIN)));
+        assert_eq!(
+            run("-9223372036854775808 == -9223372036854775807 - 1;"),
+            Ok(Object::Bool(true))
+        );
+        assert_eq!(
+            run("-0x8000_0000_0000_0000 / 2;"),
+            Ok(Object::Integer(i64::MIN / 2))
+        );
+        #[cfg(not(feature = "bigint"))]
+        for src in [
+            "9223372036854775807 + 1;",
+            "-9223372036854775807 - 2;",
+            "4611686018427387904 * 2;",
+            "-(-9223372036854775808);",
+        ] {
+            assert!(
+                matches!(run(src), Err(RuntimeError::IntegerOverflow { .. })),
+                "{}",
+                src
+            );
+        }
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn eval_bigint() {
+        let big = |s: &str| Object::BigInt(s.parse().unwrap());
+        let fact =
+            "let fact = fn(n) { let r = 1; while (n > 1) { let r = r * n; let n = n - 1; } r; }; ";
+        let run_with = |expr: &str| run(&format!("{}{}", fact, expr));
+        assert_eq!(run_with("fact(25);"), Ok(big("15511210043330985984000000")));
+        assert_eq!(
+            run_with("fact(25);").unwrap().to_string(),
+            "15511210043330985984000000"
+        );
+        // results that fit are small integers again.
+        assert_eq!(run_with("fact(25) / fact(23);"), Ok(Object::Integer(600)));
+        assert_eq!(run_with("fact(25) - fact(25);"), Ok(Object::Integer(0)));
+        assert_eq!(run_with("fact(25) % 7;"), Ok(Object::Integer(0)));
+        assert_eq!(
+            run("9223372036854775807 + 1;"),
+            Ok(big("9223372036854775808"))
+        );
+        assert_eq!(
+            run("9223372036854775808 - 1;"),
+            Ok(Object::Integer(i64::MAX))
+        );
+        assert_eq!(
+            run("0x1_0000_0000_0000_0000;"),
+            Ok(big("18446744073709551616"))
+        );
+        assert_eq!(
+            run("-(-9223372036854775808);"),
+            Ok(big("9223372036854775808"))
+        );
+        assert_eq!(
+            run("let min = -9223372036854775808; min / -1 - 1;"),
+            Ok(Object::Integer(i64::MAX))
+        );
+        assert_eq!(run_with("fact(21) > fact(20);"), Ok(Object::Bool(true)));
+        assert_eq!(run_with("1 < fact(21);"), Ok(Object::Bool(true)));
+        assert_eq!(run_with("-fact(21) <= 0;"), Ok(Object::Bool(true)));
+        assert_eq!(run_with("fact(21) == fact(21);"), Ok(Object::Bool(true)));
+        assert_eq!(run_with("{fact(21): 1}[fact(21)];"), Ok(Object::Integer(1)));
+        assert!(matches!(
+            run_with("fact(21) / 0;"),
+            Err(RuntimeError::DivisionByZero { .. })
+        ));
+        assert!(matches!(
+            run_with("fact(21) + true;"),
+            Err(RuntimeError::TypeError { op: "+", .. })
+        ));
+    }
+
+    #[test]
+    fn eval_floats() {
+        let float = |src: &str| match run(src) {
+            Ok(Object::Float(x)) => x,
+            result => panic!("{}: {:?}", src, result),
+        };
+        assert_eq!(float("1.5 + 2.25;"), 3.75);
+        assert_eq!(float("1 + 0.5;"), 1.5);
+        assert_eq!(float("3.0 * 2;"), 6.0);
+        assert_eq!(float("7 / 2.0;"), 3.5);
+        assert_eq!(float("-7.5 % 2;"), -1.5);
+        assert_eq!(float("-2.5e-3;"), -0.0025);
+        assert_eq!(float("1.0 / 0;"), f64::INFINITY);
+        assert!(float("0.0 / 0.0;").is_nan());
+        assert_eq!(run("1 == 1.0;"), Ok(Object::Bool(true)));
+        assert_eq!(run("[1, 2] == [1.0, 2];"), Ok(Object::Bool(true)));
+        assert_eq!(run("0.1 + 0.2 != 0.3;"), Ok(Object::Bool(true)));
+        assert_eq!(run("1 < 1.5;"), Ok(Object::Bool(true)));
+        assert_eq!(run("2.5 >= 3;"), Ok(Object::Bool(false)));
+        assert_eq!(
+            run("let nan = 0.0 / 0.0; nan < 1 == nan >= 1;"),
+            Ok(Object::Bool(true))
+        );
+        assert_eq!(run("if (0.0) { 1; } else { 2; }"), Ok(Object::Integer(2)));
+        assert_eq!(run("2.0;").unwrap().to_string(), "2.0");
+        assert_eq!(run("[1e21, 0.5];").unwrap().to_string(), "[1e21, 0.5]");
+        assert!(matches!(
+            run(r#"1.5 + "a";"#),
+            Err(RuntimeError::TypeError { op: "+", .. })
+        ));
+        assert!(matches!(
+            run("{1.5: 1};"),
+            Err(RuntimeError::UnhashableKey {
+                type_name: "float",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn eval_float_builtins() {
+        let int = |src: &str| match run(src) {
+            Ok(Object::Integer(i)) => i,
+            result => panic!("{}: {:?}", src, result),
+        };
+        let float = |src: &str| match run(src) {
+            Ok(Object::Float(x)) => x,
+            result => panic!("{}: {:?}", src, result),
+        };
+        assert_eq!(int("floor(-2.5);"), -3);
+        assert_eq!(int("ceil(-2.5);"), -2);
+        assert_eq!(int("round(2.5);"), 3);
+        assert_eq!(int("round(-2.5);"), -3);
+        assert_eq!(int("int(-2.9);"), -2);
+        assert_eq!(int("floor(7);"), 7);
+        assert_eq!(int("int(7);"), 7);
+        assert_eq!(float("sqrt(16);"), 4.0);
+        assert_eq!(float("float(3);"), 3.0);
+        assert!(float("sqrt(-1.0);").is_nan());
+        assert_eq!(int("[10, 20][int(1.9)];"), 20);
+        assert!(matches!(
+            run("int(1.0 / 0);"),
+            Err(RuntimeError::FloatToInt { .. })
+        ));
+        assert!(matches!(
+            run("floor(true);"),
+            Err(RuntimeError::TypeError { op: "floor", .. })
+        ));
+        #[cfg(not(feature = "bigint"))]
+        assert!(matches!(
+            run("int(1e19);"),
+            Err(RuntimeError::IntegerOverflow { .. })
+        ));
+        #[cfg(feature = "bigint")]
+        assert_eq!(
+            run("int(1e19);").unwrap().to_string(),
+            "10000000000000000000"
+        );
+    }
+
+    #[test]
+    fn eval_logical_operators() {
+        assert_eq!(run("true && 1;"), Ok(Object::Bool(true)));
+        assert_eq!(run("1 && 0;"), Ok(Object::Bool(false)));
+        assert_eq!(run("0 || \"\";"), Ok(Object::Bool(true)));
+        assert_eq!(run("false || 0.0;"), Ok(Object::Bool(false)));
+        assert_eq!(run("1 < 2 && 2 < 3 || false;"), Ok(Object::Bool(true)));
+        // the right operand is not evaluated when the left one decides.
+        assert_eq!(run("false && undefined;"), Ok(Object::Bool(false)));
+        assert_eq!(run("true || 1 / 0;"), Ok(Object::Bool(true)));
+        assert!(matches!(
+            run("true && undefined;"),
+            Err(RuntimeError::UndefinedName { .. })
+        ));
+    }
 }
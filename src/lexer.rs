@@ -1,30 +1,184 @@
-use token::{lookup_keyword, Token};
+#[cfg(feature = "bigint")]
+use num_bigint::BigInt;
+use span::{Position, Span};
+use std::error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use token::{lookup_keyword, SpannedToken, Token};
+use unicode_xid::UnicodeXID;
 
+/// What is wrong with the source at a `LexError`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LexErrorKind {
+    /// A char that starts no token, e.g. `🐒`, or a `\u{301}` combining
+    /// accent that may continue an identifier but not start one.
+    IllegalChar(char),
+    UnterminatedString,
+    /// A `/*` without a matching `*/`. Block comments nest.
+    UnterminatedComment,
+    /// An integer literal too large for a 64-bit signed integer, even when
+    /// negated. With the `bigint` feature such literals are big integers
+    /// instead, and this is never produced.
+    IntegerTooLarge,
+    /// A float literal whose value is too large to be finite.
+    FloatTooLarge,
+    /// A `0x`, `0o` or `0b` prefix with no digits after it.
+    MissingDigits {
+        radix: u32,
+    },
+    /// A letter or digit in a number literal that is not a digit of its
+    /// radix, e.g. the `2` of `0b102` or the `x` of `1x`.
+    InvalidDigit {
+        digit: char,
+        radix: u32,
+    },
+    /// A `\` followed by something other than `n`, `t`, `r`, `"`, `\`, or `u`.
+    InvalidEscape(char),
+    /// A `\u` not followed by `{`, 1 to 6 hex digits and `}` naming a
+    /// Unicode scalar value.
+    InvalidUnicodeEscape,
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LexErrorKind::IllegalChar(c) => write!(
+                f,
+                "illegal character `{}` (U+{:04X})",
+                c.escape_debug(),
+                c as u32
+            ),
+            LexErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            LexErrorKind::UnterminatedComment => write!(f, "unterminated block comment"),
+            LexErrorKind::IntegerTooLarge => write!(f, "integer literal is too large"),
+            LexErrorKind::FloatTooLarge => write!(f, "float literal is too large"),
+            LexErrorKind::MissingDigits { radix } => {
+                write!(f, "missing digits after `{}`", radix_prefix(radix))
+            }
+            LexErrorKind::InvalidDigit { digit, radix } => write!(
+                f,
+                "invalid digit `{}` in {} literal",
+                digit.escape_debug(),
+                radix_name(radix)
+            ),
+            LexErrorKind::InvalidEscape(c) => write!(f, "unknown escape sequence `\\{}`", c),
+            LexErrorKind::InvalidUnicodeEscape => write!(f, "invalid unicode escape"),
+        }
+    }
+}
+
+fn radix_prefix(radix: u32) -> &'static str {
+    match radix {
+        2 => "0b",
+        8 => "0o",
+        _ => "0x",
+    }
+}
+
+/// `binary`, `octal`, `decimal` or `hexadecimal`.
+pub fn radix_name(radix: u32) -> &'static str {
+    match radix {
+        2 => "binary",
+        8 => "octal",
+        10 => "decimal",
+        _ => "hexadecimal",
+    }
+}
+
+// the integer `digits` in `radix`, which are all valid digits. Values above
+// the magnitude of `i64::MIN` are an error unless the `bigint` feature is on.
+fn int_literal(digits: &str, radix: u32) -> Result<Token, LexErrorKind> {
+    match u64::from_str_radix(digits, radix) {
+        Ok(n) if n <= i64::MIN.unsigned_abs() => Ok(Token::Int(n)),
+        #[cfg(feature = "bigint")]
+        _ => Ok(Token::BigInt(
+            BigInt::parse_bytes(digits.as_bytes(), radix).unwrap(),
+        )),
+        #[cfg(not(feature = "bigint"))]
+        _ => Err(LexErrorKind::IntegerTooLarge),
+    }
+}
+
+/// Source the lexer could not turn into a token. `span` covers all of it,
+/// e.g. the whole of a string literal with a bad escape, and lexing resumes
+/// after it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl error::Error for LexError {}
+
+/// Lexes all of `src`. The tokens do not include a final `Token::EOF`.
+pub fn tokenize(src: &str) -> Result<Vec<SpannedToken>, LexError> {
+    Lexer::new(src).collect()
+}
+
+/// Turns source text into tokens in a single pass. Identifiers and numbers
+/// are sliced out of the source rather than collected char by char.
+///
+/// As an iterator, it yields tokens up to but excluding `Token::EOF`.
+/// Comments are skipped like whitespace unless the lexer was made with
+/// `with_comments`.
 #[derive(Debug)]
-pub struct Lexer {
-    src: String,
-    position: usize,
-    read_position: usize,
+pub struct Lexer<'a> {
+    src: &'a str,
+    keep_comments: bool,
+    chars: Peekable<Chars<'a>>,
     ch: Option<char>,
+    // where `ch` starts, and where the next char to read starts.
+    ch_pos: Position,
+    cursor: Position,
 }
 
-impl Lexer {
-    pub fn new(src: String) -> Lexer {
-        let mut l = Lexer {
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Lexer<'a> {
+        Lexer {
             src,
-            position: 0,
-            read_position: 0,
+            keep_comments: false,
+            chars: src.chars().peekable(),
             ch: None,
-        };
-        l.read_char();
-        l
+            ch_pos: Position::new(),
+            cursor: Position::new(),
+        }
     }
 
-    pub fn next_token(&mut self) -> Token {
-        self.read_char();
-        self.skip_whitespace();
+    /// Makes the lexer yield comments as `Token::Comment` trivia instead of
+    /// skipping them, e.g. for a formatter.
+    pub fn with_comments(mut self) -> Lexer<'a> {
+        self.keep_comments = true;
+        self
+    }
+
+    /// The next token, or `Token::EOF` once the input has been read.
+    pub fn next_token(&mut self) -> Result<SpannedToken, LexError> {
+        loop {
+            self.read_char();
+            self.skip_whitespace();
+            let start = self.ch_pos;
+            let span = |l: &Lexer| Span::new(start, l.cursor);
+            return match self.token(start) {
+                Ok(Token::Comment(_)) if !self.keep_comments => continue,
+                Ok(token) => Ok(SpannedToken::new(token, span(self))),
+                Err(kind) => Err(LexError {
+                    kind,
+                    span: span(self),
+                }),
+            };
+        }
+    }
 
-        match self.ch {
+    // reads the token starting with `ch`, at `start`.
+    fn token(&mut self, start: Position) -> Result<Token, LexErrorKind> {
+        let token = match self.ch {
             Some('=') => {
                 if let Some('=') = self.peek_char() {
                     self.read_char();
@@ -41,67 +195,295 @@ impl Lexer {
                     Token::Bang
                 }
             }
+            Some('&') => {
+                if let Some('&') = self.peek_char() {
+                    self.read_char();
+                    Token::And
+                } else {
+                    return Err(LexErrorKind::IllegalChar('&'));
+                }
+            }
+            Some('|') => {
+                if let Some('|') = self.peek_char() {
+                    self.read_char();
+                    Token::Or
+                } else {
+                    return Err(LexErrorKind::IllegalChar('|'));
+                }
+            }
             Some('+') => Token::Plus,
             Some('-') => Token::Minus,
             Some('*') => Token::Star,
-            Some('<') => Token::LT,
-            Some('>') => Token::GT,
+            Some('/') => match self.peek_char() {
+                Some('/') => {
+                    self.read_while(|c| c != '\n');
+                    Token::Comment(self.literal(start).to_string())
+                }
+                Some('*') => self.read_block_comment(start)?,
+                _ => Token::Slash,
+            },
+            Some('%') => Token::Percent,
+            Some('<') => {
+                if let Some('=') = self.peek_char() {
+                    self.read_char();
+                    Token::LTE
+                } else {
+                    Token::LT
+                }
+            }
+            Some('>') => {
+                if let Some('=') = self.peek_char() {
+                    self.read_char();
+                    Token::GTE
+                } else {
+                    Token::GT
+                }
+            }
             Some(';') => Token::Semicolon,
             Some('{') => Token::LBrace,
             Some('}') => Token::RBrace,
+            Some('[') => Token::LBracket,
+            Some(']') => Token::RBracket,
             Some('(') => Token::LParen,
             Some(')') => Token::RParen,
+            Some(':') => Token::Colon,
             Some(',') => Token::Comma,
+            Some('"') => self.read_string()?,
             None => Token::EOF,
             Some(c) => match c {
-                'a'..='z' | 'A'..='Z' | '_' => {
-                    let mut literal = String::new();
-                    while let Some('a'..='z') | Some('A'..='Z') | Some('_') = self.ch {
-                        literal.push_str(&self.ch.unwrap().to_string());
-                        self.read_char();
-                    }
-                    self.backtrack();
-                    lookup_keyword(literal)
+                '0'..='9' => self.read_number(start)?,
+                // identifiers follow Unicode's `XID_Start XID_Continue*`,
+                // except that they may also start with `_`.
+                _ if c == '_' || c.is_xid_start() => {
+                    self.read_while(UnicodeXID::is_xid_continue);
+                    lookup_keyword(self.literal(start))
+                }
+                _ => return Err(LexErrorKind::IllegalChar(c)),
+            },
+        };
+        Ok(token)
+    }
+
+    // reads an integer or float literal starting at `start`, whose first
+    // digit is `ch`. A `.` is part of the number only when a digit follows
+    // it, and so is an exponent: `e` and an optional sign. Digits may be
+    // separated by `_`s, which are ignored. A letter right after the number
+    // is an error, as in `0b102`, rather than the start of an identifier.
+    fn read_number(&mut self, start: Position) -> Result<Token, LexErrorKind> {
+        if self.ch == Some('0') {
+            match self.peek_char() {
+                Some('x' | 'X') => return self.read_radix_number(16),
+                Some('o' | 'O') => return self.read_radix_number(8),
+                Some('b' | 'B') => return self.read_radix_number(2),
+                _ => {}
+            }
+        }
+        let is_digit = |c: char| c.is_ascii_digit();
+        let is_digit_or_separator = |c: char| c.is_ascii_digit() || c == '_';
+        self.read_while(is_digit_or_separator);
+        let mut is_float = false;
+        if self.peek_char() == Some('.') && self.peek_nth(1).is_some_and(is_digit) {
+            self.read_char();
+            self.read_while(is_digit_or_separator);
+            is_float = true;
+        }
+        if let Some('e' | 'E') = self.peek_char() {
+            let digits_at = if let Some('+' | '-') = self.peek_nth(1) {
+                2
+            } else {
+                1
+            };
+            if self.peek_nth(digits_at).is_some_and(is_digit) {
+                for _ in 0..digits_at {
+                    self.read_char();
                 }
-                '0'..='9' => {
-                    let mut literal = String::new();
-                    while let Some('0'..='9') = self.ch {
-                        literal.push_str(&self.ch.unwrap().to_string());
-                        self.read_char();
+                self.read_while(is_digit_or_separator);
+                is_float = true;
+            }
+        }
+        if let Some(digit) = self.peek_char().filter(|&c| c.is_xid_continue()) {
+            self.read_while(UnicodeXID::is_xid_continue);
+            return Err(LexErrorKind::InvalidDigit { digit, radix: 10 });
+        }
+        let literal = self.literal(start);
+        let without_separators;
+        let literal = if literal.contains('_') {
+            without_separators = literal.replace('_', "");
+            &without_separators
+        } else {
+            literal
+        };
+        if is_float {
+            match literal.parse::<f64>() {
+                Ok(x) if x.is_finite() => Ok(Token::Float(x)),
+                _ => Err(LexErrorKind::FloatTooLarge),
+            }
+        } else {
+            int_literal(literal, 10)
+        }
+    }
+
+    // reads an integer literal in `radix`; `ch` is the `0` of its prefix.
+    // Letters and digits right after the prefix all belong to the literal,
+    // so `0b102` is an error rather than `0b10` followed by `2`.
+    fn read_radix_number(&mut self, radix: u32) -> Result<Token, LexErrorKind> {
+        self.read_char();
+        let digits_start = self.cursor;
+        self.read_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        let digits = &self.src[digits_start.offset..self.cursor.offset];
+        if let Some(digit) = digits.chars().find(|&c| c != '_' && !c.is_digit(radix)) {
+            return Err(LexErrorKind::InvalidDigit { digit, radix });
+        }
+        let digits = digits.replace('_', "");
+        if digits.is_empty() {
+            return Err(LexErrorKind::MissingDigits { radix });
+        }
+        int_literal(&digits, radix)
+    }
+
+    // reads a block comment starting at `start`, where `ch` is the `/` of
+    // its `/*`. Each `/*` inside needs its own `*/`, so commenting out code
+    // that already has comments works.
+    fn read_block_comment(&mut self, start: Position) -> Result<Token, LexErrorKind> {
+        self.read_char();
+        let mut depth = 1;
+        while depth > 0 {
+            self.read_char();
+            match (self.ch, self.peek_char()) {
+                (None, _) => return Err(LexErrorKind::UnterminatedComment),
+                (Some('/'), Some('*')) => {
+                    self.read_char();
+                    depth += 1;
+                }
+                (Some('*'), Some('/')) => {
+                    self.read_char();
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        }
+        Ok(Token::Comment(self.literal(start).to_string()))
+    }
+
+    // reads a string literal; `ch` is the opening quote. After a bad escape
+    // the rest of the literal is still consumed, so lexing resumes after it.
+    fn read_string(&mut self) -> Result<Token, LexErrorKind> {
+        let mut s = String::new();
+        let mut error = None;
+        loop {
+            self.read_char();
+            let c = match self.ch {
+                None => return Err(LexErrorKind::UnterminatedString),
+                Some('"') => break,
+                Some('\\') => {
+                    self.read_char();
+                    match self.ch {
+                        Some('n') => Ok('\n'),
+                        Some('t') => Ok('\t'),
+                        Some('r') => Ok('\r'),
+                        Some('"') => Ok('"'),
+                        Some('\\') => Ok('\\'),
+                        Some('u') => self.read_unicode_escape(),
+                        Some(c) => Err(LexErrorKind::InvalidEscape(c)),
+                        None => return Err(LexErrorKind::UnterminatedString),
                     }
-                    self.backtrack();
-                    Token::Int(literal.parse::<i32>().unwrap())
                 }
-                _ => Token::Illegal(c),
-            },
+                Some(c) => Ok(c),
+            };
+            match c {
+                Ok(c) => s.push(c),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(Token::Str(s)),
         }
     }
 
+    // reads the `{1F600}` part of a `\u{1F600}` escape.
+    fn read_unicode_escape(&mut self) -> Result<char, LexErrorKind> {
+        if self.peek_char() != Some('{') {
+            return Err(LexErrorKind::InvalidUnicodeEscape);
+        }
+        self.read_char();
+        let mut digits = String::new();
+        while let Some(c) = self.peek_char().filter(char::is_ascii_hexdigit) {
+            digits.push(c);
+            self.read_char();
+        }
+        if self.peek_char() != Some('}') || digits.is_empty() || digits.len() > 6 {
+            return Err(LexErrorKind::InvalidUnicodeEscape);
+        }
+        self.read_char();
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or(LexErrorKind::InvalidUnicodeEscape)
+    }
+
     fn skip_whitespace(&mut self) {
-        while let Some('\t') | Some(' ') | Some('\n') | Some('\r') = self.ch {
+        while let Some('\t' | ' ' | '\n' | '\r') = self.ch {
             self.read_char();
         }
     }
 
     fn read_char(&mut self) {
-        self.ch = self.src.chars().nth(self.position);
-        self.position = self.read_position;
-        self.read_position += 1;
+        self.ch = self.chars.next();
+        self.ch_pos = self.cursor;
+        if let Some(c) = self.ch {
+            self.cursor.advance(c);
+        }
     }
 
-    fn backtrack(&mut self) {
-        self.read_position -= 1;
-        self.position = self.read_position - 1;
+    // reads chars for as long as the next one satisfies `pred`.
+    fn read_while<F: Fn(char) -> bool>(&mut self, pred: F) {
+        while self.peek_char().is_some_and(&pred) {
+            self.read_char();
+        }
     }
 
-    fn peek_char(&self) -> Option<char> {
-        self.src.chars().nth(self.position)
+    // the source from `start` up to and including `ch`.
+    fn literal(&self, start: Position) -> &'a str {
+        &self.src[start.offset..self.cursor.offset]
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    // the char `n` chars after the next one.
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.chars.clone().nth(n)
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<SpannedToken, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_token() {
+            Ok(SpannedToken {
+                token: Token::EOF, ..
+            }) => None,
+            result => Some(result),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Lexer, Token};
+    use super::{tokenize, LexError, LexErrorKind, Lexer, Token};
+    use span::{Position, Span};
+
+    // the tokens of `src`, or the kinds of the errors in their place.
+    fn kinds(src: &str) -> Vec<Result<Token, LexErrorKind>> {
+        Lexer::new(src)
+            .map(|t| t.map(|t| t.token).map_err(|e| e.kind))
+            .collect()
+    }
 
     #[test]
     fn some_operand() {
@@ -112,13 +494,18 @@ mod tests {
             Token::RBrace,
             Token::LParen,
             Token::RParen,
+            Token::LBracket,
+            Token::RBracket,
+            Token::Colon,
+            Token::Slash,
+            Token::Percent,
             Token::EOF,
         ];
-        let input = "+;{}()".to_string();
+        let input = "+;{}()[]:/%";
 
         let mut l = Lexer::new(input);
         for t in expected {
-            assert_eq!(l.next_token(), t);
+            assert_eq!(l.next_token().unwrap().token, t);
         }
     }
 
@@ -130,8 +517,7 @@ mod tests {
                      let add = fn (x ,y) {\n\
                      x + y;\n\
                      };\n\
-                     let result = add(five, ten);"
-            .to_string();
+                     let result = add(five, ten);";
 
         let expected = vec![
             Token::Let,
@@ -173,7 +559,7 @@ mod tests {
         ];
         let mut l = Lexer::new(input);
         for t in expected {
-            let result = l.next_token();
+            let result = l.next_token().unwrap().token;
             assert_eq!(result, t);
         }
     }
@@ -185,8 +571,7 @@ mod tests {
                      return true;\
                      } else {\
                      return false;\
-                     }"
-        .to_string();
+                     }";
         let expected = vec![
             Token::If,
             Token::LParen,
@@ -208,7 +593,7 @@ mod tests {
         ];
         let mut l = Lexer::new(input);
         for t in expected {
-            let result = l.next_token();
+            let result = l.next_token().unwrap().token;
             assert_eq!(result, t);
         }
     }
@@ -219,8 +604,7 @@ mod tests {
         9
         !true;
         10==10;\
-        10 != 9;"
-            .to_string();
+        10 != 9;";
 
         let expected = vec![
             Token::Int(9),
@@ -239,8 +623,356 @@ mod tests {
 
         let mut l = Lexer::new(input);
         for t in expected {
-            let result = l.next_token();
+            let result = l.next_token().unwrap().token;
             assert_eq!(result, t);
         }
     }
+
+    #[test]
+    fn token_spans() {
+        let pos = |offset, line, column| Position {
+            offset,
+            line,
+            column,
+        };
+        let mut l = Lexer::new("let x\n  == 10;");
+        let expected = vec![
+            (Token::Let, Span::new(pos(0, 1, 1), pos(3, 1, 4))),
+            (
+                Token::Ident("x".to_string()),
+                Span::new(pos(4, 1, 5), pos(5, 1, 6)),
+            ),
+            (Token::Eq, Span::new(pos(8, 2, 3), pos(10, 2, 5))),
+            (Token::Int(10), Span::new(pos(11, 2, 6), pos(13, 2, 8))),
+            (Token::Semicolon, Span::new(pos(13, 2, 8), pos(14, 2, 9))),
+            (Token::EOF, Span::new(pos(14, 2, 9), pos(14, 2, 9))),
+        ];
+        for (token, span) in expected {
+            let t = l.next_token().unwrap();
+            assert_eq!(t.token, token);
+            assert_eq!(t.span, span);
+        }
+    }
+
+    #[test]
+    fn comparison_operators() {
+        let input = "< <= > >= == != = && || & |";
+        let expected = vec![
+            Ok(Token::LT),
+            Ok(Token::LTE),
+            Ok(Token::GT),
+            Ok(Token::GTE),
+            Ok(Token::Eq),
+            Ok(Token::NotEq),
+            Ok(Token::Assign),
+            Ok(Token::And),
+            Ok(Token::Or),
+            Err(LexErrorKind::IllegalChar('&')),
+            Err(LexErrorKind::IllegalChar('|')),
+        ];
+        assert_eq!(kinds(input), expected);
+    }
+
+    #[test]
+    fn string_literals() {
+        let input = r#""hello" "a\tb\n" "q\"\\" "\u{48}\u{1F600}" "" "caf\u{e9}""#;
+        let expected = vec![
+            Token::Str("hello".to_string()),
+            Token::Str("a\tb\n".to_string()),
+            Token::Str("q\"\\".to_string()),
+            Token::Str("H\u{1F600}".to_string()),
+            Token::Str("".to_string()),
+            Token::Str("café".to_string()),
+            Token::EOF,
+        ];
+        let mut l = Lexer::new(input);
+        for t in expected {
+            assert_eq!(l.next_token().unwrap().token, t);
+        }
+    }
+
+    #[test]
+    fn string_errors() {
+        let input = r#""a\qb" "\u{110000}" "\u12" 1 "open"#;
+        let expected = vec![
+            Err(LexErrorKind::InvalidEscape('q')),
+            Err(LexErrorKind::InvalidUnicodeEscape),
+            Err(LexErrorKind::InvalidUnicodeEscape),
+            Ok(Token::Int(1)),
+            Err(LexErrorKind::UnterminatedString),
+        ];
+        assert_eq!(kinds(input), expected);
+    }
+
+    #[test]
+    fn integer_literals() {
+        // the magnitude of `i64::MIN` is an integer token, as the parser
+        // accepts it when negated.
+        let input = "0 9223372036854775807 9223372036854775808;";
+        let expected = vec![
+            Ok(Token::Int(0)),
+            Ok(Token::Int(i64::MAX as u64)),
+            Ok(Token::Int(1 << 63)),
+            Ok(Token::Semicolon),
+        ];
+        assert_eq!(kinds(input), expected);
+
+        let input = "9223372036854775809 0x8000_0000_0000_0001";
+        #[cfg(not(feature = "bigint"))]
+        let expected = vec![
+            Err(LexErrorKind::IntegerTooLarge),
+            Err(LexErrorKind::IntegerTooLarge),
+        ];
+        #[cfg(feature = "bigint")]
+        let expected = vec![
+            Ok(Token::BigInt("9223372036854775809".parse().unwrap())),
+            Ok(Token::BigInt("9223372036854775809".parse().unwrap())),
+        ];
+        assert_eq!(kinds(input), expected);
+    }
+
+    #[test]
+    fn float_literals() {
+        let input = "3.25 1e9 2.5e-3 7E+2 0.5 1.x 1e 2e+ 1e999";
+        let expected = vec![
+            Ok(Token::Float(3.25)),
+            Ok(Token::Float(1e9)),
+            Ok(Token::Float(2.5e-3)),
+            Ok(Token::Float(700.0)),
+            Ok(Token::Float(0.5)),
+            Ok(Token::Int(1)),
+            Err(LexErrorKind::IllegalChar('.')),
+            Ok(Token::Ident("x".to_string())),
+            Err(LexErrorKind::InvalidDigit {
+                digit: 'e',
+                radix: 10,
+            }),
+            Err(LexErrorKind::InvalidDigit {
+                digit: 'e',
+                radix: 10,
+            }),
+            Ok(Token::Plus),
+            Err(LexErrorKind::FloatTooLarge),
+        ];
+        assert_eq!(kinds(input), expected);
+    }
+
+    #[test]
+    fn iterator_and_tokenize() {
+        let pos = |offset, line, column| Position {
+            offset,
+            line,
+            column,
+        };
+        // the iterator ends at the end of input instead of yielding `EOF`
+        let mut l = Lexer::new("x ");
+        assert_eq!(
+            l.next().unwrap().unwrap().token,
+            Token::Ident("x".to_string())
+        );
+        assert!(l.next().is_none());
+
+        let tokens = tokenize("1 + 2").unwrap();
+        assert_eq!(
+            tokens.iter().map(|t| t.token.clone()).collect::<Vec<_>>(),
+            vec![Token::Int(1), Token::Plus, Token::Int(2)]
+        );
+
+        // the first error stops `tokenize`, and carries its span
+        assert_eq!(
+            tokenize("1 & 2 |"),
+            Err(LexError {
+                kind: LexErrorKind::IllegalChar('&'),
+                span: Span::new(pos(2, 1, 3), pos(3, 1, 4)),
+            })
+        );
+        assert_eq!(
+            tokenize("\"\\q\"").unwrap_err().to_string(),
+            "unknown escape sequence `\\q`"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let input = "1 // to the end of the line\n/ 2 /* a /* nested */ one */ 3 \"// not one\" //";
+        let expected = vec![
+            Ok(Token::Int(1)),
+            Ok(Token::Slash),
+            Ok(Token::Int(2)),
+            Ok(Token::Int(3)),
+            Ok(Token::Str("// not one".to_string())),
+        ];
+        assert_eq!(kinds(input), expected);
+
+        let comments: Vec<_> = Lexer::new(input)
+            .with_comments()
+            .map(|t| t.unwrap().token)
+            .filter(|t| matches!(t, Token::Comment(_)))
+            .collect();
+        assert_eq!(
+            comments,
+            vec![
+                Token::Comment("// to the end of the line".to_string()),
+                Token::Comment("/* a /* nested */ one */".to_string()),
+                Token::Comment("//".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_comment() {
+        let pos = |offset, line, column| Position {
+            offset,
+            line,
+            column,
+        };
+        let mut l = Lexer::new("1 /* a /* b */\n2");
+        assert_eq!(l.next_token().unwrap().token, Token::Int(1));
+        assert_eq!(
+            l.next_token(),
+            Err(LexError {
+                kind: LexErrorKind::UnterminatedComment,
+                span: Span::new(pos(2, 1, 3), pos(16, 2, 2)),
+            })
+        );
+        assert_eq!(l.next_token().unwrap().token, Token::EOF);
+    }
+
+    #[test]
+    fn identifiers() {
+        let input = "x1 _ _a2 x_ café Straße π_2 変数 αβγ123abc";
+        let expected = vec![
+            "x1",
+            "_",
+            "_a2",
+            "x_",
+            "café",
+            "Straße",
+            "π_2",
+            "変数",
+            "αβγ123abc",
+        ]
+        .into_iter()
+        .map(|s| Ok(Token::Ident(s.to_string())))
+        .collect::<Vec<_>>();
+        assert_eq!(kinds(input), expected);
+    }
+
+    #[test]
+    fn illegal_chars() {
+        let input = "a 🐒 b \u{301}c \u{200B} ٣";
+        let expected = vec![
+            Ok(Token::Ident("a".to_string())),
+            Err(LexErrorKind::IllegalChar('🐒')),
+            Ok(Token::Ident("b".to_string())),
+            Err(LexErrorKind::IllegalChar('\u{301}')),
+            Ok(Token::Ident("c".to_string())),
+            Err(LexErrorKind::IllegalChar('\u{200B}')),
+            Err(LexErrorKind::IllegalChar('٣')),
+        ];
+        assert_eq!(kinds(input), expected);
+        assert_eq!(
+            LexErrorKind::IllegalChar('🐒').to_string(),
+            "illegal character `🐒` (U+1F412)"
+        );
+        assert_eq!(
+            LexErrorKind::IllegalChar('\u{200B}').to_string(),
+            "illegal character `\\u{200b}` (U+200B)"
+        );
+    }
+
+    #[test]
+    fn radix_literals() {
+        let input = "0xff 0XF 0o17 0O17 0b1010 0B11 0x7FFF_FFFF_FFFF_FFFF 0b_1 0";
+        let expected = vec![
+            Ok(Token::Int(255)),
+            Ok(Token::Int(15)),
+            Ok(Token::Int(15)),
+            Ok(Token::Int(15)),
+            Ok(Token::Int(10)),
+            Ok(Token::Int(3)),
+            Ok(Token::Int(i64::MAX as u64)),
+            Ok(Token::Int(1)),
+            Ok(Token::Int(0)),
+        ];
+        assert_eq!(kinds(input), expected);
+    }
+
+    #[test]
+    fn digit_separators() {
+        let input = "1_000_000 1__0_ 1_000.000_5 1_0e1_0 1._5 _1";
+        let expected = vec![
+            Ok(Token::Int(1_000_000)),
+            Ok(Token::Int(10)),
+            Ok(Token::Float(1_000.000_5)),
+            Ok(Token::Float(10e10)),
+            Ok(Token::Int(1)),
+            Err(LexErrorKind::IllegalChar('.')),
+            Ok(Token::Ident("_5".to_string())),
+            Ok(Token::Ident("_1".to_string())),
+        ];
+        assert_eq!(kinds(input), expected);
+    }
+
+    #[test]
+    fn malformed_radix_literals() {
+        let pos = |offset, line, column| Position {
+            offset,
+            line,
+            column,
+        };
+        let input = "0x; 0b102 0o8 0xfg 0b_ 0b1.5 00x1 1x 2.5f 1e3é 0X";
+        let expected = vec![
+            Err(LexErrorKind::MissingDigits { radix: 16 }),
+            Ok(Token::Semicolon),
+            Err(LexErrorKind::InvalidDigit {
+                digit: '2',
+                radix: 2,
+            }),
+            Err(LexErrorKind::InvalidDigit {
+                digit: '8',
+                radix: 8,
+            }),
+            Err(LexErrorKind::InvalidDigit {
+                digit: 'g',
+                radix: 16,
+            }),
+            Err(LexErrorKind::MissingDigits { radix: 2 }),
+            Ok(Token::Int(1)),
+            Err(LexErrorKind::IllegalChar('.')),
+            Ok(Token::Int(5)),
+            Err(LexErrorKind::InvalidDigit {
+                digit: 'x',
+                radix: 10,
+            }),
+            Err(LexErrorKind::InvalidDigit {
+                digit: 'x',
+                radix: 10,
+            }),
+            Err(LexErrorKind::InvalidDigit {
+                digit: 'f',
+                radix: 10,
+            }),
+            Err(LexErrorKind::InvalidDigit {
+                digit: 'é',
+                radix: 10,
+            }),
+            Err(LexErrorKind::MissingDigits { radix: 16 }),
+        ];
+        assert_eq!(kinds(input), expected);
+
+        // the error covers the whole literal
+        let mut l = Lexer::new("0b102;");
+        let e = l.next_token().unwrap_err();
+        assert_eq!(e.span, Span::new(pos(0, 1, 1), pos(5, 1, 6)));
+        assert_eq!(e.to_string(), "invalid digit `2` in binary literal");
+        assert_eq!(l.next_token().unwrap().token, Token::Semicolon);
+        let e = Lexer::new("12ab").next_token().unwrap_err();
+        assert_eq!(e.span, Span::new(pos(0, 1, 1), pos(4, 1, 5)));
+        assert_eq!(e.to_string(), "invalid digit `a` in decimal literal");
+        assert_eq!(
+            LexErrorKind::MissingDigits { radix: 8 }.to_string(),
+            "missing digits after `0o`"
+        );
+    }
 }
@@ -1,8 +1,15 @@
-#![feature(if_while_or_patterns)]
+#[cfg(feature = "bigint")]
+extern crate num_bigint;
+#[cfg(feature = "bigint")]
+extern crate num_traits;
+extern crate unicode_xid;
 
 pub mod ast;
+pub mod builtins;
 pub mod codegen;
+pub mod diagnostics;
 pub mod eval;
 pub mod lexer;
 pub mod repl;
+pub mod span;
 pub mod token;
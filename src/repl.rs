@@ -1,37 +1,105 @@
 use ast::*;
-use eval::Evaluator;
+use diagnostics::Diagnostic;
+use eval::{Evaluator, Object};
 use lexer::*;
-use token::*;
 
-#[allow(unused_imports)]
-use std::io::{self, stdin, Read, Write};
+use std::env;
+use std::fs;
+use std::io::{self, stdin, IsTerminal, Write};
+use std::panic;
+use std::thread;
 
-pub fn start() {
+// stack size of the thread that evaluates code. Calls may nest up to
+// `eval::MAX_CALL_DEPTH` deep, which takes more than the main thread's stack
+// in debug builds.
+const STACK_SIZE: usize = 256 << 20;
+
+pub fn start(color: bool) {
+    with_stack(move || repl(color))
+}
+
+fn repl(color: bool) {
     println!("Yo this is a Monkey programming language REPL!");
     println!("Feel free to type some statement!");
     let ev = Evaluator::new();
     loop {
         print!(">> ");
         io::stdout().flush().unwrap();
-        let mut l = Lexer::new(read_input());
-        let mut v = vec![];
-        loop {
-            let t = l.next_token();
-            v.push(t);
-            if v.get(v.len() - 1) == Some(&Token::EOF) {
-                break;
+        let src = match read_input() {
+            Some(src) => src,
+            None => return,
+        };
+        if let Some(obj) = run(&ev, "<repl>", &src, color) {
+            println!("{}", obj);
+        }
+    }
+}
+
+/// Evaluates the file at `path`, reporting errors on stderr. Returns whether
+/// it ran without errors.
+pub fn run_file(path: &str, color: bool) -> io::Result<bool> {
+    let src = fs::read_to_string(path)?;
+    let path = path.to_string();
+    Ok(with_stack(move || {
+        let ev = Evaluator::new();
+        run(&ev, &path, &src, color).is_some()
+    }))
+}
+
+// runs `f` on a thread with a stack of `STACK_SIZE`, and returns its result.
+fn with_stack<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let thread = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(f)
+        .expect("failed to spawn the interpreter thread");
+    match thread.join() {
+        Ok(result) => result,
+        Err(e) => panic::resume_unwind(e),
+    }
+}
+
+/// Whether diagnostics should use ANSI colors: not when asked not to, either
+/// with `--no-color` or the `NO_COLOR` environment variable, nor when stderr
+/// is not a terminal.
+pub fn color_enabled(no_color_flag: bool) -> bool {
+    !no_color_flag && env::var_os("NO_COLOR").is_none() && io::stderr().is_terminal()
+}
+
+// lexes, parses and evaluates `src`, returning the value of the last
+// statement. Errors are rendered on stderr and yield `None`.
+fn run(ev: &Evaluator, file_name: &str, src: &str, color: bool) -> Option<Object> {
+    let mut p = Parser::new(Lexer::new(src));
+    let stmts = match p.parse() {
+        Ok(stmts) => stmts,
+        Err(errors) => {
+            for e in &errors {
+                report(Diagnostic::from(e), file_name, src, color);
             }
+            return None;
         }
-        let mut p = Parser::new(&v);
-        p.parse();
-        for s in p.result {
-            println!("{:?}", ev.eval(s, &ev.global_env));
+    };
+    match ev.eval_program(stmts, &ev.global_env) {
+        Ok(obj) => Some(obj),
+        Err(e) => {
+            report(Diagnostic::from(&e), file_name, src, color);
+            None
         }
     }
 }
 
-fn read_input() -> String {
+fn report(d: Diagnostic, file_name: &str, src: &str, color: bool) {
+    eprint!("{}", d.render(file_name, src, color));
+}
+
+// reads a line from stdin, or `None` at the end of input.
+fn read_input() -> Option<String> {
     let mut s = String::new();
-    stdin().read_line(&mut s).expect("failed to read stdin");
-    s
+    match stdin().read_line(&mut s).expect("failed to read stdin") {
+        0 => None,
+        _ => Some(s),
+    }
 }
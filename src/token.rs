@@ -1,50 +1,140 @@
+#[cfg(feature = "bigint")]
+use num_bigint::BigInt;
+use span::Span;
+use std::fmt;
+
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Token {
-    Illegal(char),
     EOF,
 
     Assign,
     Plus,
     Minus,
     Star,
+    Slash,
+    Percent,
 
     GT,
+    GTE,
     LT,
+    LTE,
 
     Bang,
     Eq,
     NotEq,
+    And,
+    Or,
 
     LParen,
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
 
     Semicolon,
+    Colon,
     Comma,
 
     Let,
     Function,
     Ident(String),
-    Int(i32),
+    /// An integer literal, which is never negative. It may be one more than
+    /// `i64::MAX`, as `-9223372036854775808` is a valid integer.
+    Int(u64),
+    /// An integer literal too large for `Int`.
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt),
+    Float(f64),
+    Str(String),
     If,
     Else,
     While,
+    Break,
+    Continue,
     Return,
     True,
     False,
+
+    /// A `//` or `/* */` comment, with its delimiters. Only a lexer made
+    /// with `Lexer::with_comments` produces these.
+    Comment(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::EOF => write!(f, "EOF"),
+            Token::Assign => write!(f, "="),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Star => write!(f, "*"),
+            Token::Slash => write!(f, "/"),
+            Token::Percent => write!(f, "%"),
+            Token::GT => write!(f, ">"),
+            Token::GTE => write!(f, ">="),
+            Token::LT => write!(f, "<"),
+            Token::LTE => write!(f, "<="),
+            Token::Bang => write!(f, "!"),
+            Token::Eq => write!(f, "=="),
+            Token::NotEq => write!(f, "!="),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::LBrace => write!(f, "{{"),
+            Token::RBrace => write!(f, "}}"),
+            Token::LBracket => write!(f, "["),
+            Token::RBracket => write!(f, "]"),
+            Token::Semicolon => write!(f, ";"),
+            Token::Colon => write!(f, ":"),
+            Token::Comma => write!(f, ","),
+            Token::Let => write!(f, "let"),
+            Token::Function => write!(f, "fn"),
+            Token::Ident(ref s) => write!(f, "{}", s),
+            Token::Int(i) => write!(f, "{}", i),
+            #[cfg(feature = "bigint")]
+            Token::BigInt(ref b) => write!(f, "{}", b),
+            Token::Float(x) => write!(f, "{:?}", x),
+            Token::Str(ref s) => write!(f, "{:?}", s),
+            Token::If => write!(f, "if"),
+            Token::Else => write!(f, "else"),
+            Token::While => write!(f, "while"),
+            Token::Break => write!(f, "break"),
+            Token::Continue => write!(f, "continue"),
+            Token::Return => write!(f, "return"),
+            Token::True => write!(f, "true"),
+            Token::False => write!(f, "false"),
+            Token::Comment(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl SpannedToken {
+    pub fn new(token: Token, span: Span) -> SpannedToken {
+        SpannedToken { token, span }
+    }
 }
 
-pub fn lookup_keyword(literal: String) -> Token {
-    match &*literal {
+pub fn lookup_keyword(literal: &str) -> Token {
+    match literal {
         "let" => Token::Let,
         "fn" => Token::Function,
         "if" => Token::If,
         "else" => Token::Else,
         "while" => Token::While,
+        "break" => Token::Break,
+        "continue" => Token::Continue,
         "return" => Token::Return,
         "true" => Token::True,
         "false" => Token::False,
-        _ => Token::Ident(literal),
+        _ => Token::Ident(literal.to_string()),
     }
 }